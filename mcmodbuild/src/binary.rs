@@ -1,33 +1,51 @@
 use crate::structs::{BuildType, ModBuild};
-use anyhow::{Ok, Result};
-use oxfmt::Deserialize;
+use anyhow::Result;
+use oxfmt::{Deserializable, Deserialize, Error, Limits, Versioned};
+
+/// Build files come from mod repositories, which can't be trusted to be well formed
+const LIMITS: Limits = Limits {
+    max_len: 1024,
+    max_string_len: 64 * 1024,
+    max_total_bytes: 1024 * 1024,
+    max_depth: 16,
+};
 
 pub fn deserialize(buf: &[u8]) -> Result<ModBuild> {
-    let header = "mcmodbuild".as_bytes();
-    let version: u16 = 1;
+    Ok(ModBuild::from_bytes_with_limits(buf, LIMITS)?)
+}
+
+/// Version 1 layout, where `cmd` was only written for `BuildType::Cmd` builds
+pub struct ModBuildV1(ModBuild);
 
-    let mut deserialize = Deserialize::new(buf, header, version)?;
-    let id = deserialize.read_string()?;
-    let name = deserialize.read_string()?;
-    let git = deserialize.read_string()?;
-    let branch = deserialize.read_string()?;
-    let build_int = deserialize.read_u8()?;
-    let build = BuildType::try_from(build_int)?;
-    let cmd = match build {
-        BuildType::Cmd => Some(deserialize.read_string()?),
-        BuildType::Std => None,
-    };
-    let out = deserialize.read_string()?;
-    let exclude = deserialize.read_vec()?;
+impl<'a> Deserializable<'a> for ModBuildV1 {
+    fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+        let id = deserialize.read_string()?;
+        let name = deserialize.read_string()?;
+        let git = deserialize.read_string()?;
+        let branch = deserialize.read_string()?;
+        let build: BuildType = deserialize.read_struct()?;
+        let cmd = match build {
+            BuildType::Cmd => Some(deserialize.read_string()?),
+            BuildType::Std => None,
+        };
+        let out = deserialize.read_string()?;
+        let exclude = deserialize.read_vec()?;
+
+        Ok(Self(ModBuild {
+            id,
+            name,
+            git,
+            branch,
+            build,
+            cmd,
+            out,
+            exclude,
+        }))
+    }
+}
 
-    Ok(ModBuild {
-        id,
-        name,
-        git,
-        branch,
-        build,
-        cmd,
-        out,
-        exclude,
-    })
+impl From<ModBuildV1> for ModBuild {
+    fn from(build: ModBuildV1) -> Self {
+        build.0
+    }
 }
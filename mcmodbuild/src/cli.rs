@@ -25,4 +25,8 @@ pub enum Subcommands {
         #[clap(short = 'd')]
         destination: Option<PathBuf>,
     },
+    Schema {
+        #[clap(short = 'd')]
+        destination: Option<PathBuf>,
+    },
 }
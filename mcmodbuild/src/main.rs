@@ -12,7 +12,7 @@ use anyhow::{Ok, Result};
 use binary::deserialize;
 use clap::Parser;
 use installer::Installer;
-use oxfmt::Serializable;
+use oxfmt::{Schema, Serializable};
 use structs::ModBuild;
 
 fn main() -> Result<()> {
@@ -21,6 +21,7 @@ fn main() -> Result<()> {
     match cli.subcommand {
         cli::Subcommands::Build { file, destination } => build(file, destination),
         cli::Subcommands::Install { file, destination } => install(file, destination),
+        cli::Subcommands::Schema { destination } => schema(destination),
     }
 }
 
@@ -37,9 +38,16 @@ fn build(file: PathBuf, destination: Option<PathBuf>) -> Result<()> {
     Ok(())
 }
 
+/// Writes the schema of `.mcmodbuild` files, so they can be inspected with `oxfmt dump`
+fn schema(destination: Option<PathBuf>) -> Result<()> {
+    let destination = destination.unwrap_or(PathBuf::from("mcmodbuild.oxschema"));
+    fs::write(destination, Schema::of::<ModBuild>().serialize()?)?;
+    Ok(())
+}
+
 fn install(file: PathBuf, destination: Option<PathBuf>) -> Result<()> {
-    let content = fs::read_to_string(file)?;
-    let build = deserialize(content.as_bytes())?;
+    let content = fs::read(file)?;
+    let build = deserialize(&content)?;
 
     let id = build.id.clone();
     let branch = build.branch.clone();
@@ -54,13 +62,15 @@ fn install(file: PathBuf, destination: Option<PathBuf>) -> Result<()> {
 
 #[cfg(test)]
 mod tests {
+    use oxfmt::{BinaryBuilder, Versioned, serde::Serde};
+    use proptest::{collection::vec, prelude::*};
+
     use crate::structs::{BuildType, ExcludePair, ExcludeType};
 
     use super::*;
 
-    #[test]
-    fn roundtrip_serialize_deserialize() {
-        let build = ModBuild {
+    fn test_build() -> ModBuild {
+        ModBuild {
             id: "testmod".into(),
             name: "Test mod".into(),
             git: "https://repo.git".into(),
@@ -78,7 +88,12 @@ mod tests {
                     value: "dev-".into(),
                 },
             ],
-        };
+        }
+    }
+
+    #[test]
+    fn roundtrip_serialize_deserialize() {
+        let build = test_build();
 
         let serialized = build.clone().serialize().unwrap();
         println!("Serialized: {serialized:?}");
@@ -86,4 +101,115 @@ mod tests {
         println!("Deserialized: {deserialized:?}");
         assert_eq!(build, deserialized);
     }
+
+    #[test]
+    fn serde_matches_derives() {
+        let build = test_build();
+
+        let serialized = BinaryBuilder::new(ModBuild::HEADER, ModBuild::VERSION)
+            .add(&Serde(&build))
+            .unwrap()
+            .build();
+        assert_eq!(serialized, build.serialize().unwrap());
+    }
+
+    #[test]
+    fn roundtrip_without_cmd() {
+        let build = ModBuild {
+            build: BuildType::Std,
+            cmd: None,
+            out: "file:@/build/libs/testmod.jar".into(),
+            exclude: vec![],
+            ..test_build()
+        };
+
+        let serialized = build.serialize().unwrap();
+        let deserialized = deserialize(&serialized).unwrap();
+        assert_eq!(build, deserialized);
+    }
+
+    #[test]
+    fn invalid_build_type_is_an_error() {
+        let build = test_build();
+
+        let mut serialized = build.serialize().unwrap().into_vec();
+        // Header, version, format and flags followed by four null terminated strings
+        let strings = [&build.id, &build.name, &build.git, &build.branch];
+        let offset = "mcmodbuild".len() + 4 + strings.iter().map(|s| s.len() + 1).sum::<usize>();
+        serialized[offset] = 7;
+        let err = deserialize(&serialized).unwrap_err();
+        assert!(format!("{err:#}").contains("invalid BuildType discriminant: 7"));
+    }
+
+    #[test]
+    fn reads_version_1_files() {
+        let expected = ModBuild {
+            cmd: Some("./build.sh".into()),
+            exclude: vec![ExcludePair {
+                type_name: ExcludeType::Ends,
+                value: "-sources.jar".into(),
+            }],
+            ..test_build()
+        };
+
+        let mut buf = b"mcmodbuild".to_vec();
+        buf.extend(1u16.to_le_bytes());
+        buf.push(1); // 64-bit
+        for string in [
+            &expected.id,
+            &expected.name,
+            &expected.git,
+            &expected.branch,
+        ] {
+            buf.extend(string.as_bytes());
+            buf.push(0);
+        }
+        buf.push(BuildType::Cmd as u8);
+        buf.extend(b"./build.sh\0@/target/\0");
+        buf.extend(1u64.to_le_bytes());
+        buf.push(ExcludeType::Ends as u8);
+        buf.extend(b"-sources.jar\0");
+
+        assert_eq!(deserialize(&buf).unwrap(), expected);
+    }
+
+    fn exclude_pair() -> impl Strategy<Value = ExcludePair> {
+        let type_name = prop_oneof![
+            Just(ExcludeType::Ends),
+            Just(ExcludeType::Starts),
+            Just(ExcludeType::Contains),
+        ];
+        (type_name, any::<String>()).prop_map(|(type_name, value)| ExcludePair { type_name, value })
+    }
+
+    fn modbuild() -> impl Strategy<Value = ModBuild> {
+        let build = prop_oneof![Just(BuildType::Cmd), Just(BuildType::Std)];
+        (
+            any::<[String; 4]>(),
+            build,
+            any::<Option<String>>(),
+            any::<String>(),
+            vec(exclude_pair(), 0..8),
+        )
+            .prop_map(
+                |([id, name, git, branch], build, cmd, out, exclude)| ModBuild {
+                    id,
+                    name,
+                    git,
+                    branch,
+                    build,
+                    cmd,
+                    out,
+                    exclude,
+                },
+            )
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_builds_roundtrip(build in modbuild()) {
+            let serialized = build.serialize().unwrap();
+            prop_assert_eq!(deserialize(&serialized).unwrap(), build);
+        }
+    }
 }
@@ -1,25 +1,20 @@
-use oxfmt::{Deserializable, Field, Serializable};
+use crate::binary::ModBuildV1;
+use oxfmt::{Describe, Deserializable, Serializable};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Serializable)]
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Serializable, Deserializable, Describe,
+)]
 #[repr(u8)]
 pub enum BuildType {
     Cmd = 0,
     Std = 1,
 }
 
-impl From<u8> for BuildType {
-    fn from(v: u8) -> Self {
-        match v {
-            0 => BuildType::Cmd,
-            1 => BuildType::Std,
-            other => panic!("invalid build type: {other}"),
-        }
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Serializable)]
-#[oxfmt(header = "mcmodbuild", version = 1)]
+#[derive(
+    Debug, Serialize, Deserialize, Clone, PartialEq, Serializable, Deserializable, Describe,
+)]
+#[oxfmt(header = "mcmodbuild", version = 2, upgrade(1 = ModBuildV1))]
 pub struct ModBuild {
     pub id: String,
     pub name: String,
@@ -31,30 +26,21 @@ pub struct ModBuild {
     pub exclude: Vec<ExcludePair>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Serializable, Deserializable)]
+#[derive(
+    Debug, Deserialize, Serialize, Clone, PartialEq, Serializable, Deserializable, Describe,
+)]
 pub struct ExcludePair {
     #[serde(rename = "type")]
-    #[oxfmt(mapping = Field::U8, from = u8)]
     pub type_name: ExcludeType,
-    #[oxfmt(mapping = Field::String)]
     pub value: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Serializable)]
+#[derive(
+    Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Serializable, Deserializable, Describe,
+)]
 #[repr(u8)]
 pub enum ExcludeType {
     Ends = 0,
     Starts = 1,
     Contains = 2,
 }
-
-impl From<u8> for ExcludeType {
-    fn from(v: u8) -> Self {
-        match v {
-            0 => ExcludeType::Ends,
-            1 => ExcludeType::Starts,
-            2 => ExcludeType::Contains,
-            other => panic!("invalid build type: {other}"),
-        }
-    }
-}
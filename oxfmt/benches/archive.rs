@@ -0,0 +1,80 @@
+//! Writing and reading an archive of named entries followed by one large blob, with
+//! blobs from a few hundred KiB to several MiB. Run with `cargo bench`.
+
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use oxfmt::{Deserializable, Serializable, Versioned};
+
+#[derive(Serializable, Deserializable)]
+#[oxfmt(header = "bench", version = 1, checksum)]
+struct Archive {
+    entries: Vec<Entry>,
+    blob: Box<[u8]>,
+}
+
+#[derive(Serializable, Deserializable)]
+struct Entry {
+    name: String,
+    kind: u8,
+    offset: u64,
+    data: Box<[u8]>,
+}
+
+/// An archive with a `blob_len` byte blob and an entry with a small payload for every
+/// KiB of it
+fn archive(blob_len: usize) -> Archive {
+    Archive {
+        entries: (0..blob_len / 1024)
+            .map(|i| Entry {
+                name: format!("entries/entry-{i}"),
+                kind: (i % 3) as u8,
+                offset: (i * 1024) as u64,
+                data: vec![i as u8; 64].into_boxed_slice(),
+            })
+            .collect(),
+        blob: (0..blob_len).map(|i| (i * 31 % 251) as u8).collect(),
+    }
+}
+
+const SIZES: [usize; 3] = [256 << 10, 1 << 20, 8 << 20];
+
+fn write(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write");
+    for len in SIZES {
+        let archive = archive(len);
+        group.throughput(Throughput::Bytes(archive.size_hint() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("serialize", len),
+            &archive,
+            |b, archive| b.iter(|| archive.serialize().unwrap()),
+        );
+        // Without the size hint, growing the buffer as it goes
+        group.bench_with_input(BenchmarkId::new("unsized", len), &archive, |b, archive| {
+            b.iter(|| {
+                let mut buf = Vec::new();
+                archive.serialize_into(&mut buf).unwrap();
+                buf
+            })
+        });
+    }
+    group.finish();
+}
+
+fn read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read");
+    for len in SIZES {
+        let buf = archive(len).serialize().unwrap();
+        group.throughput(Throughput::Bytes(buf.len() as u64));
+        group.bench_with_input(BenchmarkId::new("from_bytes", len), &buf, |b, buf| {
+            b.iter(|| Archive::from_bytes(black_box(buf)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("from_reader", len), &buf, |b, buf| {
+            b.iter(|| Archive::from_reader(black_box(&buf[..])).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, write, read);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+#![no_main]
+
+use std::collections::BTreeMap;
+
+use libfuzzer_sys::fuzz_target;
+use oxfmt::{Describe, Deserializable, Schema, Serializable, Versioned};
+
+#[derive(Debug, Clone, Copy, Serializable, Deserializable, Describe)]
+#[repr(u8)]
+enum Kind {
+    First = 0,
+    Last = 9,
+}
+
+#[derive(Debug, Serializable, Deserializable, Describe)]
+enum Entry {
+    Empty,
+    Named { name: String, weight: Option<f64> },
+    Pair(i64, Vec<bool>),
+}
+
+// No checksum, so mutated inputs get past the header and into the fields
+#[derive(Debug, Serializable, Deserializable, Describe)]
+#[oxfmt(header = "fuzz", version = 1)]
+struct Fuzzed {
+    kind: Kind,
+    title: String,
+    entries: Vec<Entry>,
+    index: BTreeMap<String, Vec<Option<u32>>>,
+    blob: Box<[u8]>,
+    count: usize,
+    wide: (u128, i16),
+}
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Fuzzed::from_bytes(data);
+    let _ = Fuzzed::from_reader(data);
+    let _ = Schema::of::<Fuzzed>().read(data);
+});
@@ -1,34 +1,231 @@
-use crate::types::Serializable;
-use anyhow::Result;
+use std::io::{self, Read, Write};
 
-pub struct BinaryBuilder {
-    buf: Vec<u8>,
+#[cfg(any(feature = "zstd", feature = "deflate"))]
+use crate::format::Compression;
+use crate::{
+    format::{FORMAT_VERSION, TRAILER_LEN, flags, varint},
+    types::Serializable,
+};
+use anyhow::{Result, bail};
+
+pub struct BinaryBuilder<W: Write = Vec<u8>> {
+    writer: Tracked<Output<W>>,
+    /// Header, version and format version, held back until the flags are known
+    meta: Option<Vec<u8>>,
+    flags: u8,
 }
 
 impl BinaryBuilder {
     pub fn new(header: &[u8], version: u16) -> Self {
-        let mut buf = Vec::new();
-        buf.extend(header);
-        buf.extend(version.to_le_bytes());
-        let arch: u8 = match size_of::<usize>() {
-            4 => 0, // 32-bit
-            8 => 1, // 64-bit
-            _ => panic!("invalid architecture: {}", size_of::<usize>()),
-        };
-        buf.push(arch);
-        Self { buf }
+        Self::from_writer(Vec::new(), header, version)
     }
 
     pub fn new_no_meta() -> Self {
-        Self { buf: Vec::new() }
+        Self::from_writer_no_meta(Vec::new())
     }
 
-    pub fn add<T: Serializable>(mut self, serializeable: &T) -> Result<Self> {
-        self.buf.extend(serializeable.serialize()?);
-        Ok(self)
+    /// Like `new`, with room for `capacity` bytes so adding fields doesn't reallocate. A
+    /// type's [`Serializable::size_hint`] is a good estimate.
+    pub fn with_capacity(header: &[u8], version: u16, capacity: usize) -> Self {
+        Self::from_writer(Vec::with_capacity(capacity), header, version)
+    }
+
+    /// Bytes written besides the fields: the header, version, format version and flags, and
+    /// the trailer if there's a checksum
+    pub fn framing_len(header: &[u8], checksum: bool) -> usize {
+        let trailer = if checksum { TRAILER_LEN } else { 0 };
+        header.len() + 4 + trailer
     }
 
     pub fn build(self) -> Box<[u8]> {
-        self.buf.into_boxed_slice()
+        self.finish()
+            .expect("writing to a Vec cannot fail")
+            .into_boxed_slice()
+    }
+}
+
+impl<W: Write> BinaryBuilder<W> {
+    /// Streams the output into `writer` instead of collecting it in memory
+    pub fn from_writer(writer: W, header: &[u8], version: u16) -> Self {
+        let mut meta = header.to_vec();
+        meta.extend(version.to_le_bytes());
+        meta.push(FORMAT_VERSION);
+        Self {
+            meta: Some(meta),
+            ..Self::from_writer_no_meta(writer)
+        }
+    }
+
+    pub fn from_writer_no_meta(writer: W) -> Self {
+        Self {
+            writer: Tracked {
+                inner: Output::Plain(writer),
+                len: 0,
+                hasher: None,
+            },
+            meta: None,
+            flags: 0,
+        }
+    }
+
+    /// Ends the output with its length and CRC32 so truncation and corruption can be detected
+    pub fn with_checksum(mut self) -> Self {
+        assert!(
+            self.meta.is_some(),
+            "checksums need a header and must be enabled before adding fields"
+        );
+        self.flags |= flags::CHECKSUM;
+        self.writer.hasher = Some(crc32fast::Hasher::new());
+        self
+    }
+
+    /// Compresses everything after the header, which readers undo by themselves
+    #[cfg(any(feature = "zstd", feature = "deflate"))]
+    pub fn with_compression(mut self, compression: Compression) -> Result<Self> {
+        assert!(
+            self.meta.is_some(),
+            "compression needs a header and must be enabled before adding fields"
+        );
+        let Output::Plain(writer) = self.writer.inner else {
+            panic!("compression can only be enabled once");
+        };
+        self.flags |= compression.flag();
+        self.writer.inner = match compression {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Output::Zstd(zstd::Encoder::new(writer, 0)?),
+            #[cfg(feature = "deflate")]
+            Compression::Deflate => Output::Deflate(flate2::write::DeflateEncoder::new(
+                writer,
+                flate2::Compression::default(),
+            )),
+        };
+        Ok(self)
+    }
+
+    pub fn add<T: Serializable + ?Sized>(mut self, serializeable: &T) -> Result<Self> {
+        self.write_meta()?;
+        serializeable.serialize_into(&mut self.writer)?;
+        Ok(self)
+    }
+
+    /// Adds a value written by `write`, for fields with a custom encoding
+    pub fn add_with<F>(mut self, write: F) -> Result<Self>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<()>,
+    {
+        self.write_meta()?;
+        write(&mut self.writer)?;
+        Ok(self)
+    }
+
+    /// Copies `len` bytes from `reader` as a blob, encoded the same way as a `Box<[u8]>`
+    pub fn add_blob_from<R: Read>(mut self, reader: R, len: u64) -> Result<Self> {
+        self.write_meta()?;
+        self.writer.write_all(&varint(len))?;
+        let copied = io::copy(&mut reader.take(len), &mut self.writer)?;
+        if copied != len {
+            bail!("blob ended after {} of {} bytes", copied, len)
+        }
+        Ok(self)
+    }
+
+    /// Writes the checksum trailer, if any, and returns the writer
+    pub fn finish(mut self) -> Result<W> {
+        self.write_meta()?;
+        if let Some(hasher) = self.writer.hasher.take() {
+            let len = self.writer.len;
+            self.writer.write_all(&len.to_le_bytes())?;
+            self.writer.write_all(&hasher.finalize().to_le_bytes())?;
+        }
+        Ok(self.writer.inner.finish()?)
+    }
+
+    fn write_meta(&mut self) -> Result<()> {
+        if let Some(mut meta) = self.meta.take() {
+            meta.push(self.flags);
+            // The meta is never compressed, readers need the flags to know how to go on
+            if let Some(hasher) = &mut self.writer.hasher {
+                hasher.update(&meta);
+            }
+            self.writer.len += meta.len() as u64;
+            self.writer.inner.get_mut().write_all(&meta)?;
+        }
+        Ok(())
+    }
+}
+
+/// The writer behind a `BinaryBuilder`, compressing what's written to it if enabled
+enum Output<W: Write> {
+    Plain(W),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, W>),
+    #[cfg(feature = "deflate")]
+    Deflate(flate2::write::DeflateEncoder<W>),
+}
+
+impl<W: Write> Output<W> {
+    /// The underlying writer, for writing past the compression
+    fn get_mut(&mut self) -> &mut W {
+        match self {
+            Output::Plain(writer) => writer,
+            #[cfg(feature = "zstd")]
+            Output::Zstd(encoder) => encoder.get_mut(),
+            #[cfg(feature = "deflate")]
+            Output::Deflate(encoder) => encoder.get_mut(),
+        }
+    }
+
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Output::Plain(writer) => Ok(writer),
+            #[cfg(feature = "zstd")]
+            Output::Zstd(encoder) => encoder.finish(),
+            #[cfg(feature = "deflate")]
+            Output::Deflate(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(writer) => writer.write(buf),
+            #[cfg(feature = "zstd")]
+            Output::Zstd(encoder) => encoder.write(buf),
+            #[cfg(feature = "deflate")]
+            Output::Deflate(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(writer) => writer.flush(),
+            #[cfg(feature = "zstd")]
+            Output::Zstd(encoder) => encoder.flush(),
+            #[cfg(feature = "deflate")]
+            Output::Deflate(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Counts and optionally hashes everything written through it, for the checksum trailer
+struct Tracked<W> {
+    inner: W,
+    len: u64,
+    hasher: Option<crc32fast::Hasher>,
+}
+
+impl<W: Write> Write for Tracked<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..written]);
+        }
+        self.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
     }
 }
@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+#[derive(Debug, Clone, Parser)]
+#[clap(version)]
+pub struct OxfmtCli {
+    #[clap(subcommand)]
+    pub subcommand: Subcommands,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Subcommands {
+    /// Decodes a file with whichever of the schemas has its header and prints it
+    Dump {
+        file: PathBuf,
+
+        #[clap(long = "schema", value_name = "SCHEMA", required = true)]
+        schemas: Vec<PathBuf>,
+
+        #[clap(long)]
+        json: bool,
+
+        /// Most items in a collection or entries in a map
+        #[clap(long)]
+        max_len: Option<usize>,
+
+        /// Longest string, in bytes
+        #[clap(long)]
+        max_string_len: Option<usize>,
+
+        /// Most bytes read in total
+        #[clap(long)]
+        max_total_bytes: Option<u64>,
+
+        /// Deepest nesting of structs, enums and collections
+        #[clap(long)]
+        max_depth: Option<usize>,
+    },
+}
@@ -1,163 +1,1358 @@
-use std::any::Any;
+use std::{
+    borrow::Cow,
+    io::{self, BufRead, BufReader, Read, Write},
+    ops::RangeInclusive,
+};
 
-use anyhow::{bail, Result};
+#[cfg(any(feature = "zstd", feature = "deflate"))]
+use crate::format::Compression;
+use crate::{
+    error::{Error, ErrorKind, Result},
+    format::{FORMAT_VERSION, MAX_VARINT_LEN, TRAILER_LEN, flags},
+    limits::Limits,
+    types::Deserializable,
+};
 
-use crate::types::{Deserializable, Field};
+/// Most bytes allocated for a collection before any of its items have been read
+const MAX_PREALLOC: usize = 1 << 20;
 
 pub struct Deserialize<'a> {
-    buf: &'a [u8],
-    arch: Option<u8>,
+    source: Source<'a>,
+    version: u16,
+    format: u8,
+    /// Width of `usize` in format 1 buffers
+    usize_width: usize,
+    limits: Limits,
+    /// How many structs, enums and collections are being read
+    depth: usize,
+}
+
+enum Source<'a> {
+    /// The whole buffer, without any checksum trailer, and the position of the next read
+    Slice {
+        buf: &'a [u8],
+        pos: usize,
+    },
+    Stream(Stream<'a>),
 }
 
 impl<'a> Deserialize<'a> {
     pub fn new(buf: &'a [u8], header: &[u8], version: u16) -> Result<Self> {
-        Self { buf, arch: None }.init(header, version)
+        Self::new_versioned(buf, header, version..=version)
+    }
+
+    /// Accepts any version in `versions`, the one that was read is available from `version`
+    pub fn new_versioned(
+        buf: &'a [u8],
+        header: &[u8],
+        versions: RangeInclusive<u16>,
+    ) -> Result<Self> {
+        Self::new_with_limits(buf, header, versions, Limits::default())
+    }
+
+    /// Like `new_versioned`, but compressed buffers are decompressed within `limits` when
+    /// they're opened
+    pub fn new_with_limits(
+        buf: &'a [u8],
+        header: &[u8],
+        versions: RangeInclusive<u16>,
+        limits: Limits,
+    ) -> Result<Self> {
+        Self::with_source(Source::Slice { buf, pos: 0 })
+            .with_limits(limits)
+            .init(header, versions)
+    }
+
+    /// Reads from a stream instead of a buffer, call `finish` after the last read to verify
+    /// the checksum trailer and that nothing follows the data
+    pub fn from_reader<R: Read + 'a>(
+        reader: R,
+        header: &[u8],
+        versions: RangeInclusive<u16>,
+    ) -> Result<Self> {
+        let stream = Stream {
+            reader: Box::new(BufReader::new(reader)),
+            len: 0,
+            hasher: Some(crc32fast::Hasher::new()),
+        };
+        Self::with_source(Source::Stream(stream)).init(header, versions)
+    }
+
+    fn with_source(source: Source<'a>) -> Self {
+        Self {
+            source,
+            version: 0,
+            format: FORMAT_VERSION,
+            usize_width: size_of::<usize>(),
+            limits: Limits::default(),
+            depth: 0,
+        }
+    }
+
+    /// Rejects data that goes over `limits`, for reading files from untrusted sources. Use
+    /// `new_with_limits` for compressed buffers, which are decompressed when they're opened.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Position of the next read from the start of the data
+    pub fn offset(&self) -> u64 {
+        match &self.source {
+            Source::Slice { pos, .. } => *pos as u64,
+            Source::Stream(stream) => stream.len,
+        }
     }
 
     pub fn read_string(&mut self) -> Result<String> {
-        match self.buf.iter().position(|&byte| byte == 0) {
-            Some(pos) => {
-                let string = str::from_utf8(&self.buf[..pos])?.to_string();
-                self.advance(pos + 1);
-                Ok(string)
-            }
-            None => {
-                bail!("unterminated c-string")
+        Ok(self.read_cow_str()?.into_owned())
+    }
+
+    /// Reads a string borrowed from the buffer, which fails on streams
+    pub fn read_str(&mut self) -> Result<&'a str> {
+        self.assert_borrowable()?;
+        match self.read_cow_str()? {
+            Cow::Borrowed(string) => Ok(string),
+            Cow::Owned(_) => unreachable!("buffers are always borrowed from"),
+        }
+    }
+
+    /// Borrows the string from a buffer or copies it out of a stream
+    pub fn read_cow_str(&mut self) -> Result<Cow<'a, str>> {
+        let offset = self.offset();
+        let string = match self.read_string_bytes()? {
+            Cow::Borrowed(bytes) => str::from_utf8(bytes).map(Cow::Borrowed),
+            Cow::Owned(bytes) => String::from_utf8(bytes)
+                .map(Cow::Owned)
+                .map_err(|err| err.utf8_error()),
+        };
+        string.map_err(|err| Error::new(ErrorKind::InvalidUtf8(err), offset))
+    }
+
+    fn read_string_bytes(&mut self) -> Result<Cow<'a, [u8]>> {
+        let offset = self.offset();
+        let max = self.limits.max_string_len;
+        if self.format >= 4 {
+            let len = self.read_usize()?;
+            if len > max {
+                return Err(limit_exceeded("string length", max as u64, offset));
             }
+            return self.read_cow_bytes_of(len);
         }
+
+        // Older formats wrote NUL terminated strings
+        let eof = || Error::new(ErrorKind::UnexpectedEof, offset);
+        let bytes = match &mut self.source {
+            Source::Slice { buf, pos } => {
+                let whole: &'a [u8] = buf;
+                let rest = &whole[*pos..];
+                let len = rest.iter().position(|&byte| byte == 0).ok_or_else(eof)?;
+                if len > max {
+                    return Err(limit_exceeded("string length", max as u64, offset));
+                }
+                *pos += len + 1;
+                Cow::Borrowed(&rest[..len])
+            }
+            Source::Stream(stream) => {
+                let mut bytes = Vec::new();
+                let terminated = stream
+                    .read_until(0, &mut bytes, max)
+                    .map_err(|kind| Error::new(kind, offset))?;
+                if !terminated {
+                    return Err(eof());
+                }
+                bytes.pop();
+                Cow::Owned(bytes)
+            }
+        };
+        self.check_total(0)?;
+        Ok(bytes)
     }
 
     pub fn read_u8(&mut self) -> Result<u8> {
-        let bytes = self.read_bytes(1)?;
-        Ok(u8::from_le_bytes(bytes.try_into()?))
+        Ok(u8::from_le_bytes(self.read_array()?))
     }
 
     pub fn read_u16(&mut self) -> Result<u16> {
-        let bytes = self.read_bytes(2)?;
-        Ok(u16::from_le_bytes(bytes.try_into()?))
+        Ok(u16::from_le_bytes(self.read_array()?))
     }
 
     pub fn read_u32(&mut self) -> Result<u32> {
-        let bytes = self.read_bytes(4)?;
-        Ok(u32::from_le_bytes(bytes.try_into()?))
+        Ok(u32::from_le_bytes(self.read_array()?))
     }
 
     pub fn read_u64(&mut self) -> Result<u64> {
-        let bytes = self.read_bytes(8)?;
-        Ok(u64::from_le_bytes(bytes.try_into()?))
+        Ok(u64::from_le_bytes(self.read_array()?))
     }
 
     pub fn read_u128(&mut self) -> Result<u128> {
-        let bytes = self.read_bytes(16)?;
-        Ok(u128::from_le_bytes(bytes.try_into()?))
-    }
-
-    pub fn read_struct<T: Deserializable>(&mut self) -> Result<T> {
-        let structure = T::get_structure();
-
-        let mut fields = Vec::new();
-
-        for i in 0..structure.fields.len() {
-            let field: Box<dyn Any> = match structure.fields.get(&i) {
-                Some(field) => match field {
-                    Field::String => Box::new(self.read_string()?),
-                    Field::U8 => Box::new(self.read_u8()?),
-                    Field::U16 => Box::new(self.read_u16()?),
-                    Field::U32 => Box::new(self.read_u32()?),
-                    Field::U64 => Box::new(self.read_u64()?),
-                    Field::U128 => Box::new(self.read_u128()?),
-                    Field::Struct => Box::new(self.read_struct::<T>()?),
-                    Field::Vector => Box::new(self.read_vec::<T>()?),
-                },
-                None => bail!("field index {} not found", i),
-            };
-            fields.push(field);
+        Ok(u128::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_i8(&mut self) -> Result<i8> {
+        Ok(i8::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16> {
+        Ok(i16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_i64(&mut self) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_i128(&mut self) -> Result<i128> {
+        Ok(i128::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool> {
+        let offset = self.offset();
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(Error::new(
+                ErrorKind::InvalidDiscriminant { ty: "bool", value },
+                offset,
+            )),
         }
+    }
+
+    pub fn read_struct<T: Deserializable<'a>>(&mut self) -> Result<T> {
+        T::deserialize(self)
+    }
+
+    pub fn read_vec<T: Deserializable<'a>>(&mut self) -> Result<Vec<T>> {
+        self.nested(|deserialize| {
+            let size = deserialize.read_len()?;
+            let mut vec: Vec<T> = Vec::with_capacity(deserialize.capacity_hint::<T>(size));
 
-        T::construct(fields)
+            for i in 0..size {
+                vec.push(T::deserialize(deserialize).map_err(|err| err.at_index(i))?);
+            }
+
+            Ok(vec)
+        })
     }
 
-    pub fn read_vec<T: Deserializable>(&mut self) -> Result<Vec<T>> {
-        let size = self.read_usize()?;
-        let mut vec: Vec<T> = Vec::with_capacity(size);
+    /// Reads the length of a collection, checked against the maximum length
+    pub fn read_len(&mut self) -> Result<usize> {
+        let offset = self.offset();
+        let len = self.read_usize()?;
+        if len > self.limits.max_len {
+            return Err(limit_exceeded(
+                "collection length",
+                self.limits.max_len as u64,
+                offset,
+            ));
+        }
+        Ok(len)
+    }
 
-        for _ in 0..size {
-            vec.push(self.read_struct()?);
+    /// Runs `read` one level deeper, failing instead once the maximum depth is reached. Types
+    /// that contain other values should read them through this so recursion is bounded.
+    pub fn nested<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= self.limits.max_depth {
+            let max = self.limits.max_depth as u64;
+            return Err(limit_exceeded("nesting depth", max, self.offset()));
         }
+        self.depth += 1;
+        let result = read(self);
+        self.depth -= 1;
+        result
+    }
 
-        Ok(vec)
+    /// How many of `len` items to allocate up front. The length comes from the data, so it's
+    /// capped by the bytes left in a buffer and by [`MAX_PREALLOC`] rather than trusted
+    pub(crate) fn capacity_hint<T>(&self, len: usize) -> usize {
+        let remaining = match &self.source {
+            Source::Slice { buf, pos } => buf.len() - pos,
+            Source::Stream(_) => usize::MAX,
+        };
+        len.min(remaining).min(MAX_PREALLOC / size_of::<T>().max(1))
+    }
+
+    pub fn read_option<T: Deserializable<'a>>(&mut self) -> Result<Option<T>> {
+        let offset = self.offset();
+        match self.read_u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(T::deserialize(self)?)),
+            value => Err(Error::new(
+                ErrorKind::InvalidDiscriminant {
+                    ty: "Option",
+                    value,
+                },
+                offset,
+            )),
+        }
     }
 
     pub fn read_usize(&mut self) -> Result<usize> {
-        let size = match self.arch {
-            Some(arch) => match arch {
-                0 => 4, // 32-bit
-                1 => 8, // 64-bit
-                _ => bail!("invalid architecture {}", arch),
+        let offset = self.offset();
+        let value = match self.format {
+            1 => match self.usize_width {
+                4 => self.read_u32()? as u64,
+                _ => self.read_u64()?,
             },
-            None => bail!("no architecture specified"),
+            _ => self.read_varint()?,
         };
 
-        let bytes = self.read_bytes(size)?;
+        usize::try_from(value).map_err(|_| Error::new(ErrorKind::SizeOverflow, offset))
+    }
 
-        let value = match size {
-            4 => {
-                let arr: [u8; 4] = bytes.try_into()?;
-                u32::from_le_bytes(arr) as u64
+    pub(crate) fn read_varint(&mut self) -> Result<u64> {
+        let offset = self.offset();
+        let mut value: u64 = 0;
+        for i in 0..MAX_VARINT_LEN {
+            let byte = self.read_u8()?;
+            let bits = (byte & 0x7f) as u64;
+            if i == MAX_VARINT_LEN - 1 && bits > 1 {
+                break;
             }
-            8 => {
-                let arr: [u8; 8] = bytes.try_into()?;
-                u64::from_le_bytes(arr)
+            value |= bits << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok(value);
             }
-            _ => bail!("unsupported size: {}", size),
-        };
+        }
+        Err(Error::new(ErrorKind::VarintOverflow, offset))
+    }
 
-        let usize_val =
-            usize::try_from(value).map_err(|_| anyhow::anyhow!("value does not fit in usize"))?;
+    /// Reads a length-prefixed byte blob, borrowing it from the underlying buffer
+    pub fn read_blob(&mut self) -> Result<&'a [u8]> {
+        let len = self.read_usize()?;
+        self.read_bytes(len)
+    }
 
-        if usize::BITS < 64 && value > u32::MAX as u64 {
-            bail!("value does not fit in 32-bit usize");
+    /// Borrows a length-prefixed byte blob from a buffer or copies it out of a stream
+    pub fn read_cow_bytes(&mut self) -> Result<Cow<'a, [u8]>> {
+        let len = self.read_usize()?;
+        self.read_cow_bytes_of(len)
+    }
+
+    fn read_cow_bytes_of(&mut self, len: usize) -> Result<Cow<'a, [u8]>> {
+        if let Source::Slice { .. } = self.source {
+            return Ok(Cow::Borrowed(self.read_bytes(len)?));
         }
+        let mut bytes = Vec::new();
+        self.copy_bytes_to(len, &mut bytes)?;
+        Ok(Cow::Owned(bytes))
+    }
+
+    /// Copies a length-prefixed byte blob into `writer` and returns its length, streams are
+    /// copied in chunks so the blob is never held in memory
+    pub fn read_blob_into<W: Write + ?Sized>(&mut self, writer: &mut W) -> Result<usize> {
+        let len = self.read_usize()?;
+        self.copy_bytes_to(len, writer)?;
+        Ok(len)
+    }
 
-        Ok(usize_val)
+    /// Skips over `len` bytes, streams are read in chunks without holding the bytes
+    pub fn skip(&mut self, len: usize) -> Result<()> {
+        self.copy_bytes_to(len, &mut io::sink())
     }
-    pub fn read_bytes(&mut self, bytes: usize) -> Result<&[u8]> {
-        if self.buf.len() < bytes {
-            bail!("not enough bytes")
+
+    fn copy_bytes_to<W: Write + ?Sized>(&mut self, len: usize, writer: &mut W) -> Result<()> {
+        let offset = self.offset();
+        self.check_total(len)?;
+        if let Source::Stream(stream) = &mut self.source {
+            stream
+                .copy_to(len, writer)
+                .map_err(|kind| Error::new(kind, offset))
+        } else {
+            writer
+                .write_all(self.read_bytes(len)?)
+                .map_err(|err| Error::new(ErrorKind::Io(err), offset))
+        }
+    }
+
+    /// Fails if reading `len` more bytes would go over the maximum total size
+    fn check_total(&self, len: usize) -> Result<()> {
+        let offset = self.offset();
+        let max = self.limits.max_total_bytes;
+        if offset.saturating_add(len as u64) > max {
+            return Err(limit_exceeded("total size", max, offset));
         }
-        let result = &self.buf[..bytes];
-        self.advance(bytes);
+        Ok(())
+    }
+
+    pub fn read_bytes(&mut self, bytes: usize) -> Result<&'a [u8]> {
+        let offset = self.offset();
+        self.check_total(bytes)?;
+        let Source::Slice { buf, pos } = &mut self.source else {
+            return Err(Error::new(ErrorKind::CannotBorrow, offset));
+        };
+        let buf: &'a [u8] = buf;
+        if buf.len() - *pos < bytes {
+            return Err(Error::new(ErrorKind::UnexpectedEof, offset));
+        }
+        let result = &buf[*pos..*pos + bytes];
+        *pos += bytes;
         Ok(result)
     }
 
-    fn advance(&mut self, len: usize) {
-        self.buf = &self.buf[len..];
+    fn assert_borrowable(&self) -> Result<()> {
+        if let Source::Stream(stream) = &self.source {
+            return Err(Error::new(ErrorKind::CannotBorrow, stream.len));
+        }
+        Ok(())
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn read_exact(&mut self, out: &mut [u8]) -> Result<()> {
+        let offset = self.offset();
+        self.check_total(out.len())?;
+        match &mut self.source {
+            Source::Slice { .. } => out.copy_from_slice(self.read_bytes(out.len())?),
+            Source::Stream(stream) => stream
+                .read_exact(out)
+                .map_err(|kind| Error::new(kind, offset))?,
+        }
+        Ok(())
+    }
+
+    /// Verifies the checksum trailer of a stream and checks that the data was read to its end.
+    /// The trailers of buffers are verified when they're opened.
+    pub fn finish(mut self) -> Result<()> {
+        let stream = match &mut self.source {
+            Source::Slice { buf, pos } if *pos != buf.len() => {
+                let len = (buf.len() - *pos) as u64;
+                return Err(Error::new(ErrorKind::TrailingBytes(len), *pos as u64));
+            }
+            Source::Slice { .. } => return Ok(()),
+            Source::Stream(stream) => stream,
+        };
+        if let Some(hasher) = stream.hasher.take() {
+            let len = stream.len;
+            let mut trailer = [0; TRAILER_LEN];
+            if stream.read_exact(&mut trailer).is_err() {
+                return Err(Error::new(ErrorKind::MissingChecksum, len));
+            }
+            check_trailer(&trailer, len, hasher.finalize())
+                .map_err(|kind| Error::new(kind, len))?;
+        }
+        let offset = stream.len;
+        let len = io::copy(&mut stream.reader, &mut io::sink())
+            .map_err(|err| Error::new(ErrorKind::Io(err), offset))?;
+        if len > 0 {
+            return Err(Error::new(ErrorKind::TrailingBytes(len), offset));
+        }
+        Ok(())
     }
 
     fn assert_header(&mut self, header: &[u8]) -> Result<()> {
-        if !self.buf.starts_with(header) {
-            bail!("buffer does not start with header")
+        let mut found = vec![0; header.len()];
+        if self.read_exact(&mut found).is_err() || found != header {
+            return Err(Error::new(ErrorKind::BadHeader, 0));
         }
-        self.advance(header.len());
         Ok(())
     }
 
-    fn assert_version(&mut self, version: u16) -> Result<()> {
-        if self.read_u16()? != version {
-            bail!("version does not match")
+    fn read_version(&mut self, versions: RangeInclusive<u16>) -> Result<()> {
+        let offset = self.offset();
+        let version = self.read_u16()?;
+        if !versions.contains(&version) {
+            return Err(Error::new(
+                ErrorKind::VersionMismatch {
+                    found: version,
+                    supported: versions,
+                },
+                offset,
+            ));
         }
+        self.version = version;
         Ok(())
     }
 
-    fn read_arch(&mut self) -> Result<()> {
-        self.arch = Some(self.read_u8()?);
+    fn read_format(&mut self) -> Result<()> {
+        let offset = self.offset();
+        match self.read_u8()? {
+            // Format 1 stored the pointer width of the writer instead of a format version
+            0 => (self.format, self.usize_width) = (1, 4),
+            1 => (self.format, self.usize_width) = (1, 8),
+            format @ 2..=FORMAT_VERSION => self.format = format,
+            format => return Err(Error::new(ErrorKind::UnsupportedFormat(format), offset)),
+        }
         Ok(())
     }
 
-    fn init(mut self, header: &[u8], version: u16) -> Result<Self> {
+    fn read_flags(&mut self) -> Result<u8> {
+        if self.format < 3 {
+            return Ok(0);
+        }
+        let offset = self.offset();
+        let flags = self.read_u8()?;
+        let compressions = flags & (flags::ZSTD | flags::DEFLATE);
+        if flags & !flags::KNOWN != 0 || compressions.count_ones() > 1 {
+            return Err(Error::new(ErrorKind::UnsupportedFlags(flags), offset));
+        }
+        #[cfg(not(feature = "zstd"))]
+        if flags & flags::ZSTD != 0 {
+            return Err(Error::new(
+                ErrorKind::UnsupportedCompression("zstd"),
+                offset,
+            ));
+        }
+        #[cfg(not(feature = "deflate"))]
+        if flags & flags::DEFLATE != 0 {
+            return Err(Error::new(
+                ErrorKind::UnsupportedCompression("deflate"),
+                offset,
+            ));
+        }
+        Ok(flags)
+    }
+
+    fn init(mut self, header: &[u8], versions: RangeInclusive<u16>) -> Result<Self> {
         self.assert_header(header)?;
-        self.assert_version(version)?;
-        self.read_arch()?;
+        self.read_version(versions)?;
+        self.read_format()?;
+        let flags = self.read_flags()?;
+        let checksum = flags & flags::CHECKSUM != 0;
+        #[cfg(any(feature = "zstd", feature = "deflate"))]
+        if let Some(compression) = Compression::from_flags(flags) {
+            self.decompress(compression, checksum)?;
+            return Ok(self);
+        }
+        match &mut self.source {
+            // Buffers are checked against their trailer up front, which is then dropped
+            Source::Slice { buf, pos } if checksum => {
+                let whole: &'a [u8] = buf;
+                if whole.len() - *pos < TRAILER_LEN {
+                    let len = whole.len() as u64;
+                    return Err(Error::new(ErrorKind::MissingChecksum, len));
+                }
+                let (data, trailer) = whole.split_at(whole.len() - TRAILER_LEN);
+                let len = data.len() as u64;
+                check_trailer(trailer, len, crc32fast::hash(data))
+                    .map_err(|kind| Error::new(kind, len))?;
+                *buf = data;
+            }
+            // Streams are hashed as they're read and verified by `finish` instead
+            Source::Stream(stream) if !checksum => stream.hasher = None,
+            _ => {}
+        }
         Ok(self)
     }
+
+    /// Reads the rest through a decompressor. The trailer is compressed along with the data
+    /// and covers the uncompressed bytes, so buffers are decompressed whole and verified here,
+    /// while streams are verified by `finish`.
+    #[cfg(any(feature = "zstd", feature = "deflate"))]
+    fn decompress(&mut self, compression: Compression, checksum: bool) -> Result<()> {
+        let offset = self.offset();
+        let source = std::mem::replace(&mut self.source, Source::Slice { buf: &[], pos: 0 });
+        let (reader, hasher, from_buffer): (Box<dyn BufRead + 'a>, _, _) = match source {
+            Source::Slice { buf, pos } => {
+                let mut hasher = crc32fast::Hasher::new();
+                hasher.update(&buf[..pos]);
+                (Box::new(&buf[pos..]), Some(hasher), true)
+            }
+            Source::Stream(mut stream) => (stream.reader, stream.hasher.take(), false),
+        };
+        let mut reader: Box<dyn BufRead + 'a> = match compression {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                let decoder = zstd::Decoder::with_buffer(reader)
+                    .map_err(|err| Error::new(ErrorKind::Io(err), offset))?;
+                Box::new(BufReader::new(decoder))
+            }
+            #[cfg(feature = "deflate")]
+            Compression::Deflate => {
+                Box::new(BufReader::new(flate2::bufread::DeflateDecoder::new(reader)))
+            }
+        };
+        let mut hasher = hasher.filter(|_| checksum);
+        if from_buffer {
+            let data = self.decompress_all(&mut reader, offset, hasher.take())?;
+            reader = Box::new(io::Cursor::new(data));
+        }
+        self.source = Source::Stream(Stream {
+            reader,
+            len: offset,
+            hasher,
+        });
+        Ok(())
+    }
+
+    /// Decompresses the rest of a buffer within the total size limit, then checks and drops
+    /// its trailer
+    #[cfg(any(feature = "zstd", feature = "deflate"))]
+    fn decompress_all(
+        &self,
+        reader: &mut dyn BufRead,
+        offset: u64,
+        hasher: Option<crc32fast::Hasher>,
+    ) -> Result<Vec<u8>> {
+        let max = self.limits.max_total_bytes;
+        let trailer_len = if hasher.is_some() { TRAILER_LEN } else { 0 };
+        let allowed = max
+            .saturating_sub(offset)
+            .saturating_add(trailer_len as u64);
+        let mut data = Vec::new();
+        reader
+            .take(allowed.saturating_add(1))
+            .read_to_end(&mut data)
+            .map_err(|err| Error::new(ErrorKind::Io(err), offset))?;
+        if data.len() as u64 > allowed {
+            return Err(limit_exceeded("total size", max, offset));
+        }
+        let Some(mut hasher) = hasher else {
+            return Ok(data);
+        };
+        let Some(data_len) = data.len().checked_sub(TRAILER_LEN) else {
+            let len = offset + data.len() as u64;
+            return Err(Error::new(ErrorKind::MissingChecksum, len));
+        };
+        let (body, trailer) = data.split_at(data_len);
+        hasher.update(body);
+        let len = offset + body.len() as u64;
+        check_trailer(trailer, len, hasher.finalize()).map_err(|kind| Error::new(kind, len))?;
+        data.truncate(data_len);
+        Ok(data)
+    }
+}
+
+fn limit_exceeded(limit: &'static str, max: u64, offset: u64) -> Error {
+    Error::new(ErrorKind::LimitExceeded { limit, max }, offset)
+}
+
+fn check_trailer(trailer: &[u8], len: u64, checksum: u32) -> Result<(), ErrorKind> {
+    let (expected_len, expected_checksum) = trailer.split_at(8);
+    let expected_len = u64::from_le_bytes(expected_len.try_into().expect("trailer is 12 bytes"));
+    if expected_len != len {
+        return Err(ErrorKind::LengthMismatch {
+            expected: expected_len,
+            found: len,
+        });
+    }
+    let expected_checksum = expected_checksum.try_into().expect("trailer is 12 bytes");
+    if u32::from_le_bytes(expected_checksum) != checksum {
+        return Err(ErrorKind::ChecksumMismatch);
+    }
+    Ok(())
+}
+
+/// A reader source that keeps the length and CRC32 of everything consumed from it. Its errors
+/// are given an offset by `Deserialize`.
+struct Stream<'a> {
+    reader: Box<dyn BufRead + 'a>,
+    len: u64,
+    hasher: Option<crc32fast::Hasher>,
+}
+
+impl Stream<'_> {
+    /// Hands the buffered bytes to `take`, which returns how many of them it used. Returns 0
+    /// at the end of the stream.
+    fn consume_with(
+        &mut self,
+        take: impl FnOnce(&[u8]) -> Result<usize, ErrorKind>,
+    ) -> Result<usize, ErrorKind> {
+        let buf = self.reader.fill_buf().map_err(ErrorKind::Io)?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let used = take(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..used]);
+        }
+        self.len += used as u64;
+        self.reader.consume(used);
+        Ok(used)
+    }
+
+    fn read_exact(&mut self, out: &mut [u8]) -> Result<(), ErrorKind> {
+        let mut filled = 0;
+        while filled < out.len() {
+            let used = self.consume_with(|buf| {
+                let used = buf.len().min(out.len() - filled);
+                out[filled..filled + used].copy_from_slice(&buf[..used]);
+                Ok(used)
+            })?;
+            if used == 0 {
+                return Err(ErrorKind::UnexpectedEof);
+            }
+            filled += used;
+        }
+        Ok(())
+    }
+
+    /// Reads up to and including `delim`, returns false if the stream ended first. Fails once
+    /// more than `max` bytes come before `delim`.
+    fn read_until(&mut self, delim: u8, out: &mut Vec<u8>, max: usize) -> Result<bool, ErrorKind> {
+        let mut found = false;
+        while !found {
+            let used = self.consume_with(|buf| {
+                let used = match buf.iter().position(|&byte| byte == delim) {
+                    Some(pos) => {
+                        found = true;
+                        pos + 1
+                    }
+                    None => buf.len(),
+                };
+                if out.len() + used > max.saturating_add(found as usize) {
+                    return Err(ErrorKind::LimitExceeded {
+                        limit: "string length",
+                        max: max as u64,
+                    });
+                }
+                out.extend(&buf[..used]);
+                Ok(used)
+            })?;
+            if used == 0 {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn copy_to<W: Write + ?Sized>(&mut self, len: usize, writer: &mut W) -> Result<(), ErrorKind> {
+        let mut remaining = len;
+        while remaining > 0 {
+            let used = self.consume_with(|buf| {
+                let used = buf.len().min(remaining);
+                writer.write_all(&buf[..used]).map_err(ErrorKind::Io)?;
+                Ok(used)
+            })?;
+            if used == 0 {
+                return Err(ErrorKind::UnexpectedEof);
+            }
+            remaining -= used;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use proptest::{
+        collection::{btree_map, btree_set, vec},
+        prelude::*,
+    };
+
+    use super::*;
+    use crate::{BinaryBuilder, ErrorKind, Serializable, Versioned};
+
+    #[test]
+    fn blobs_are_delimited() {
+        let first: Box<[u8]> = Box::new([1, 2, 3]);
+        let second: Box<[u8]> = Box::new([]);
+        let buf = BinaryBuilder::new(b"blobs", 1)
+            .add(&first)
+            .unwrap()
+            .add(&second)
+            .unwrap()
+            .add(&7u8)
+            .unwrap()
+            .build();
+
+        let mut deserialize = Deserialize::new(&buf, b"blobs", 1).unwrap();
+        let first = deserialize.read_blob().unwrap();
+        let second = deserialize.read_blob().unwrap();
+        assert_eq!(first, &[1, 2, 3]);
+        assert_eq!(second, &[]);
+        assert_eq!(deserialize.read_u8().unwrap(), 7);
+    }
+
+    #[test]
+    fn sizes_are_portable() {
+        let buf = BinaryBuilder::new(b"sizes", 1)
+            .add(&300usize)
+            .unwrap()
+            .add(&vec![1u8, 2])
+            .unwrap()
+            .build();
+        assert_eq!(
+            &buf[b"sizes".len()..],
+            &[1, 0, FORMAT_VERSION, 0, 0xac, 0x02, 2, 1, 2]
+        );
+
+        let mut deserialize = Deserialize::new(&buf, b"sizes", 1).unwrap();
+        assert_eq!(deserialize.read_usize().unwrap(), 300);
+        assert_eq!(deserialize.read_usize().unwrap(), 2);
+    }
+
+    #[test]
+    fn reads_format_1_sizes() {
+        let mut buf = b"sizes".to_vec();
+        buf.extend(1u16.to_le_bytes());
+        buf.push(0);
+        buf.extend(300u32.to_le_bytes());
+        let mut deserialize = Deserialize::new(&buf, b"sizes", 1).unwrap();
+        assert_eq!(deserialize.read_usize().unwrap(), 300);
+
+        let mut buf = b"sizes".to_vec();
+        buf.extend(1u16.to_le_bytes());
+        buf.push(1);
+        buf.extend(300u64.to_le_bytes());
+        let mut deserialize = Deserialize::new(&buf, b"sizes", 1).unwrap();
+        assert_eq!(deserialize.read_usize().unwrap(), 300);
+    }
+
+    #[test]
+    fn checksum_detects_truncation_and_corruption() {
+        let blob: Box<[u8]> = Box::new([7; 64]);
+        let buf = BinaryBuilder::new(b"checked", 1)
+            .with_checksum()
+            .add(&blob)
+            .unwrap()
+            .build();
+
+        let mut deserialize = Deserialize::new(&buf, b"checked", 1).unwrap();
+        assert_eq!(deserialize.read_blob().unwrap(), &*blob);
+        assert!(deserialize.read_u8().is_err());
+
+        let err = Deserialize::new(&buf[..buf.len() - 20], b"checked", 1).err();
+        assert!(err.unwrap().to_string().contains("truncated"));
+
+        let mut corrupt = buf.to_vec();
+        corrupt[20] ^= 1;
+        let err = Deserialize::new(&corrupt, b"checked", 1).err();
+        assert!(err.unwrap().to_string().contains("checksum does not match"));
+    }
+
+    #[test]
+    fn streams_read_like_buffers() {
+        let blob: Box<[u8]> = Box::new([7; 64]);
+        let buf = BinaryBuilder::from_writer(Vec::new(), b"streamed", 1)
+            .with_checksum()
+            .add(&String::from("name"))
+            .unwrap()
+            .add_blob_from(&blob[..], blob.len() as u64)
+            .unwrap()
+            .finish()
+            .unwrap();
+
+        let mut deserialize = Deserialize::from_reader(&buf[..], b"streamed", 1..=1).unwrap();
+        assert_eq!(deserialize.read_string().unwrap(), "name");
+        let mut copied = Vec::new();
+        deserialize.read_blob_into(&mut copied).unwrap();
+        assert_eq!(&*copied, &*blob);
+        deserialize.finish().unwrap();
+
+        let mut deserialize = Deserialize::from_reader(&buf[..], b"streamed", 1..=1).unwrap();
+        assert!(deserialize.read_blob().is_err());
+
+        let mut corrupt = buf.clone();
+        corrupt[20] ^= 1;
+        let mut deserialize = Deserialize::from_reader(&corrupt[..], b"streamed", 1..=1).unwrap();
+        deserialize.read_string().unwrap();
+        deserialize.read_blob_into(&mut Vec::new()).unwrap();
+        let err = deserialize.finish().unwrap_err();
+        assert!(err.to_string().contains("checksum does not match"));
+    }
+
+    #[test]
+    #[cfg(all(feature = "zstd", feature = "deflate"))]
+    fn compressed_data_reads_like_plain() {
+        let blob: Box<[u8]> = Box::new([7; 4096]);
+        for compression in [Compression::Zstd, Compression::Deflate] {
+            let buf = BinaryBuilder::new(b"packed", 1)
+                .with_checksum()
+                .with_compression(compression)
+                .unwrap()
+                .add(&String::from("name"))
+                .unwrap()
+                .add(&blob)
+                .unwrap()
+                .build();
+            assert!(buf.len() < 200);
+            assert_eq!(buf[9], flags::CHECKSUM | compression.flag());
+
+            let mut deserialize = Deserialize::new(&buf, b"packed", 1).unwrap();
+            assert_eq!(deserialize.read_string().unwrap(), "name");
+            assert_eq!(
+                &*Box::<[u8]>::deserialize(&mut deserialize).unwrap(),
+                &*blob
+            );
+            deserialize.finish().unwrap();
+
+            let mut deserialize = Deserialize::from_reader(&buf[..], b"packed", 1..=1).unwrap();
+            assert_eq!(deserialize.read_string().unwrap(), "name");
+            let mut copied = Vec::new();
+            deserialize.read_blob_into(&mut copied).unwrap();
+            assert_eq!(&*copied, &*blob);
+            deserialize.finish().unwrap();
+
+            // Nothing can be borrowed once decompressed
+            let mut deserialize = Deserialize::new(&buf, b"packed", 1).unwrap();
+            deserialize.read_string().unwrap();
+            assert!(matches!(
+                deserialize.read_blob().unwrap_err().kind(),
+                ErrorKind::CannotBorrow
+            ));
+
+            let mut corrupt = buf.to_vec();
+            let last = corrupt.len() - 5;
+            corrupt[last] ^= 1;
+            assert!(Deserialize::new(&corrupt, b"packed", 1).is_err());
+        }
+
+        // Buffers are checked against their trailer when they're opened, even if the corrupt
+        // data still decompresses
+        let plain = BinaryBuilder::new(b"packed", 1)
+            .with_checksum()
+            .add(&String::from("name"))
+            .unwrap()
+            .build();
+        let mut data = plain[10..].to_vec();
+        data[1] ^= 1;
+        let mut corrupt = plain[..10].to_vec();
+        corrupt[9] |= flags::ZSTD;
+        corrupt.extend(zstd::encode_all(&*data, 0).unwrap());
+        let err = Deserialize::new(&corrupt, b"packed", 1).err().unwrap();
+        assert!(matches!(err.kind(), ErrorKind::ChecksumMismatch));
+
+        // and have to be read to the end like any other buffer
+        let buf = BinaryBuilder::new(b"packed", 1)
+            .with_compression(Compression::Deflate)
+            .unwrap()
+            .add(&1u8)
+            .unwrap()
+            .add(&2u8)
+            .unwrap()
+            .build();
+        let mut deserialize = Deserialize::new(&buf, b"packed", 1).unwrap();
+        deserialize.read_u8().unwrap();
+        let err = deserialize.finish().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::TrailingBytes(1)));
+    }
+
+    #[test]
+    fn compression_flags_are_checked() {
+        // Only one compression can be set
+        let mut buf = BinaryBuilder::new(b"packed", 1).build().into_vec();
+        buf[9] = flags::ZSTD | flags::DEFLATE;
+        let err = Deserialize::new(&buf, b"packed", 1).err().unwrap();
+        assert!(matches!(err.kind(), ErrorKind::UnsupportedFlags(_)));
+
+        // Compressions left out of the build are reported instead of read as plain data
+        for (flag, name, enabled) in [
+            (flags::ZSTD, "zstd", cfg!(feature = "zstd")),
+            (flags::DEFLATE, "deflate", cfg!(feature = "deflate")),
+        ] {
+            if enabled {
+                continue;
+            }
+            buf[9] = flag;
+            let err = Deserialize::new(&buf, b"packed", 1).err().unwrap();
+            assert!(matches!(err.kind(), ErrorKind::UnsupportedCompression(n) if *n == name));
+        }
+    }
+
+    #[test]
+    fn signed_floats_and_bools() {
+        let buf = BinaryBuilder::new(b"numbers", 1)
+            .add(&-2i8)
+            .unwrap()
+            .add(&i128::MIN)
+            .unwrap()
+            .add(&-0.25f32)
+            .unwrap()
+            .add(&f64::MAX)
+            .unwrap()
+            .add(&true)
+            .unwrap()
+            .add(&2u8)
+            .unwrap()
+            .build();
+
+        let mut deserialize = Deserialize::new(&buf, b"numbers", 1).unwrap();
+        assert_eq!(deserialize.read_i8().unwrap(), -2);
+        assert_eq!(deserialize.read_i128().unwrap(), i128::MIN);
+        assert_eq!(deserialize.read_f32().unwrap(), -0.25);
+        assert_eq!(deserialize.read_f64().unwrap(), f64::MAX);
+        assert!(deserialize.read_bool().unwrap());
+        assert!(deserialize.read_bool().is_err());
+    }
+
+    #[test]
+    fn strings_can_contain_nul() {
+        let string = String::from("before\0after");
+        let buf = BinaryBuilder::new(b"strings", 1)
+            .add(&string)
+            .unwrap()
+            .add(&7u8)
+            .unwrap()
+            .build();
+        let mut deserialize = Deserialize::new(&buf, b"strings", 1).unwrap();
+        assert_eq!(deserialize.read_string().unwrap(), string);
+        assert_eq!(deserialize.read_u8().unwrap(), 7);
+
+        let mut buf = b"strings".to_vec();
+        buf.extend(1u16.to_le_bytes());
+        buf.extend([3, 0]);
+        buf.extend(b"c-string\0");
+        let mut deserialize = Deserialize::new(&buf, b"strings", 1).unwrap();
+        assert_eq!(deserialize.read_string().unwrap(), "c-string");
+    }
+
+    #[derive(Debug, PartialEq, crate::Serializable, crate::Deserializable)]
+    #[oxfmt(header = "view", version = 1)]
+    struct View<'a> {
+        name: &'a str,
+        author: Cow<'a, str>,
+        data: &'a [u8],
+    }
+
+    #[test]
+    fn borrows_from_buffers() {
+        let data = [9; 32];
+        let view = View {
+            name: "pack",
+            author: "me".into(),
+            data: &data,
+        };
+        let buf = view.serialize().unwrap();
+
+        let read = View::from_bytes(&buf).unwrap();
+        assert_eq!(read, view);
+        assert!(buf.as_ptr_range().contains(&read.data.as_ptr()));
+        assert!(matches!(read.author, Cow::Borrowed(_)));
+
+        let err = View::from_reader(&buf[..]).unwrap_err();
+        assert!(format!("{err:#}").contains("cannot borrow from a stream"));
+
+        let mut deserialize = Deserialize::from_reader(&buf[..], b"view", 1..=1).unwrap();
+        deserialize.read_string().unwrap();
+        let author: Cow<str> = deserialize.read_struct().unwrap();
+        assert!(matches!(author, Cow::Owned(_)));
+    }
+
+    #[derive(crate::Serializable, crate::Deserializable)]
+    #[oxfmt(header = "outer", version = 1)]
+    struct Outer {
+        items: Vec<Inner>,
+    }
+
+    #[derive(crate::Serializable, crate::Deserializable)]
+    struct Inner {
+        name: String,
+        enabled: bool,
+    }
+
+    #[test]
+    fn errors_have_offset_and_path() {
+        let outer = Outer {
+            items: vec![
+                Inner {
+                    name: "a".into(),
+                    enabled: true,
+                },
+                Inner {
+                    name: "b".into(),
+                    enabled: false,
+                },
+            ],
+        };
+        let mut buf = outer.serialize().unwrap().into_vec();
+        let last = buf.len() - 1;
+        buf[last] = 2;
+
+        let err = Outer::from_bytes(&buf).err().unwrap();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::InvalidDiscriminant {
+                ty: "bool",
+                value: 2
+            }
+        ));
+        assert_eq!(err.path(), "items[1].enabled");
+        assert_eq!(err.offset(), last as u64);
+        assert_eq!(
+            err.to_string(),
+            format!("invalid bool discriminant: 2 in `items[1].enabled` at byte {last}")
+        );
+
+        let err = Outer::from_bytes(&buf[..last]).err().unwrap();
+        assert!(matches!(err.kind(), ErrorKind::UnexpectedEof));
+        assert_eq!(err.path(), "items[1].enabled");
+    }
+
+    #[derive(Debug, Clone, PartialEq, crate::Serializable, crate::Deserializable)]
+    enum Entry {
+        Empty,
+        Named { name: String, weight: Option<f64> },
+        Pair(i64, Vec<bool>),
+    }
+
+    #[derive(Debug, Clone, PartialEq, crate::Serializable, crate::Deserializable)]
+    #[oxfmt(header = "nested", version = 1, checksum)]
+    struct Nested {
+        title: String,
+        entries: Vec<Entry>,
+        index: BTreeMap<String, Vec<Option<u32>>>,
+        tags: BTreeSet<(u8, i128)>,
+        blob: Box<[u8]>,
+        count: usize,
+    }
+
+    fn entry() -> impl Strategy<Value = Entry> {
+        prop_oneof![
+            Just(Entry::Empty),
+            (any::<String>(), any::<Option<f64>>())
+                .prop_map(|(name, weight)| Entry::Named { name, weight }),
+            (any::<i64>(), vec(any::<bool>(), 0..8)).prop_map(|(a, b)| Entry::Pair(a, b)),
+        ]
+    }
+
+    fn nested() -> impl Strategy<Value = Nested> {
+        (
+            any::<String>(),
+            vec(entry(), 0..8),
+            btree_map(any::<String>(), vec(any::<Option<u32>>(), 0..8), 0..8),
+            btree_set(any::<(u8, i128)>(), 0..8),
+            vec(any::<u8>(), 0..64),
+            any::<usize>(),
+        )
+            .prop_map(|(title, entries, index, tags, blob, count)| Nested {
+                title,
+                entries,
+                index,
+                tags,
+                blob: blob.into_boxed_slice(),
+                count,
+            })
+    }
+
+    /// NaN weights never compare equal, so they're compared by their bits instead
+    fn same(a: &Nested, b: &Nested) -> bool {
+        a.serialize().unwrap() == b.serialize().unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn values_roundtrip(value in nested()) {
+            let buf = value.serialize().unwrap();
+            prop_assert_eq!(value.size_hint(), buf.len());
+            prop_assert!(same(&Nested::from_bytes(&buf).unwrap(), &value));
+            prop_assert!(same(&Nested::from_reader(&*buf).unwrap(), &value));
+        }
+
+        #[test]
+        fn malformed_input_is_an_error(value in nested(), index: prop::sample::Index, byte: u8) {
+            let mut buf = value.serialize().unwrap().into_vec();
+            let index = index.index(buf.len());
+            buf[index] ^= byte | 1;
+            prop_assert!(Nested::from_bytes(&buf).is_err());
+            prop_assert!(Nested::from_reader(&*buf).is_err());
+            prop_assert!(Nested::from_bytes(&buf[..index]).is_err());
+        }
+
+        #[test]
+        fn random_bytes_never_panic(bytes: Vec<u8>) {
+            let mut buf = BinaryBuilder::new(b"nested", 1).build().into_vec();
+            buf.extend(bytes);
+            let _ = Nested::from_bytes(&buf);
+            let _ = Nested::from_reader(&*buf);
+        }
+    }
+
+    #[test]
+    fn huge_lengths_are_not_preallocated() {
+        let buf = BinaryBuilder::new(b"huge", 1)
+            .add(&(u64::MAX >> 1))
+            .unwrap()
+            .build();
+        // Overwrite the fixed width number with a varint of the same length
+        let mut buf = buf.into_vec();
+        let len = buf.len();
+        buf[len - 8..].copy_from_slice(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]);
+
+        let mut deserialize = Deserialize::new(&buf, b"huge", 1).unwrap();
+        let err = deserialize.read_vec::<u128>().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::UnexpectedEof));
+
+        let stream = Deserialize::from_reader(&*buf, b"huge", 1..=1);
+        let err = stream.unwrap().read_vec::<u128>().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn limits_are_checked_before_reading() {
+        let outer = Outer {
+            items: vec![
+                Inner {
+                    name: "first".into(),
+                    enabled: true,
+                },
+                Inner {
+                    name: "second".into(),
+                    enabled: false,
+                },
+            ],
+        };
+        let buf = outer.serialize().unwrap();
+        let read = |limits: Limits| {
+            let err = Outer::from_bytes_with_limits(&buf, limits).err().unwrap();
+            let stream_err = Outer::from_reader_with_limits(&*buf, limits).err().unwrap();
+            assert_eq!(err.to_string(), stream_err.to_string());
+            err
+        };
+
+        let err = read(Limits {
+            max_len: 1,
+            ..Limits::default()
+        });
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::LimitExceeded {
+                limit: "collection length",
+                max: 1
+            }
+        ));
+        assert_eq!(err.path(), "items");
+
+        let err = read(Limits {
+            max_string_len: 5,
+            ..Limits::default()
+        });
+        assert_eq!(err.path(), "items[1].name");
+
+        let err = read(Limits {
+            max_total_bytes: buf.len() as u64 - 1,
+            ..Limits::default()
+        });
+        assert_eq!(err.path(), "items[1].enabled");
+
+        let err = read(Limits {
+            max_depth: 2,
+            ..Limits::default()
+        });
+        assert_eq!(
+            err.to_string(),
+            "nesting depth is over the limit of 2 in `items[0]` at byte 10"
+        );
+
+        let limits = Limits {
+            max_len: 2,
+            max_string_len: 6,
+            max_total_bytes: buf.len() as u64,
+            max_depth: 3,
+        };
+        assert!(Outer::from_bytes_with_limits(&buf, limits).is_ok());
+        assert!(Outer::from_reader_with_limits(&*buf, limits).is_ok());
+    }
+
+    #[derive(crate::Serializable, crate::Deserializable)]
+    #[oxfmt(header = "tree", version = 1)]
+    struct Tree {
+        children: Vec<Tree>,
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        // Every byte starts another vector holding one more tree
+        let mut buf = BinaryBuilder::new(b"tree", 1).build().into_vec();
+        buf.resize(buf.len() + 100_000, 1);
+
+        let err = Tree::from_bytes(&buf).err().unwrap();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::LimitExceeded {
+                limit: "nesting depth",
+                max: 128
+            }
+        ));
+        let err = Tree::from_reader(&*buf).err().unwrap();
+        assert!(matches!(err.kind(), ErrorKind::LimitExceeded { .. }));
+    }
+
+    #[derive(crate::Serializable, crate::Deserializable)]
+    #[oxfmt(header = "point", version = 1)]
+    struct Point {
+        x: u8,
+    }
+
+    #[test]
+    fn trailing_bytes_are_an_error() {
+        let mut buf = Point { x: 1 }.serialize().unwrap().into_vec();
+        Point::from_bytes(&buf).unwrap();
+        buf.extend([9, 9, 9]);
+        let err = Point::from_bytes(&buf).err().unwrap();
+        assert!(matches!(err.kind(), ErrorKind::TrailingBytes(3)));
+        assert_eq!(err.offset(), buf.len() as u64 - 3);
+
+        // Streams are held to the same end, with or without a checksum
+        let err = Point::from_reader(&*buf).err().unwrap();
+        assert!(matches!(err.kind(), ErrorKind::TrailingBytes(3)));
+        assert_eq!(err.offset(), buf.len() as u64 - 3);
+        let mut checked = BinaryBuilder::new(b"point", 1)
+            .with_checksum()
+            .add(&1u8)
+            .unwrap()
+            .build()
+            .into_vec();
+        checked.push(9);
+        let mut deserialize = Deserialize::from_reader(&*checked, b"point", 1..=1).unwrap();
+        deserialize.read_u8().unwrap();
+        let err = deserialize.finish().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::TrailingBytes(1)));
+        assert_eq!(err.offset(), checked.len() as u64 - 1);
+
+        // Errors reading what's left aren't mistaken for the end
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("disconnected"))
+            }
+        }
+        let plain = BinaryBuilder::new(b"point", 1).add(&1u8).unwrap().build();
+        let reader = (&*plain).chain(Failing);
+        let err = Point::from_reader(reader).err().unwrap();
+        assert!(matches!(err.kind(), ErrorKind::Io(_)));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[derive(Debug, PartialEq, crate::Serializable, crate::Deserializable)]
+    #[oxfmt(header = "archive", version = 1, checksum, compress = "zstd")]
+    struct Archive {
+        files: Vec<String>,
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn limits_bound_decompressed_data() {
+        let archive = Archive {
+            files: vec![String::from("mods/sodium.jar"); 1000],
+        };
+        let buf = archive.serialize().unwrap();
+        assert!(buf.len() < 200);
+        assert_eq!(Archive::from_bytes(&buf).unwrap(), archive);
+        assert_eq!(Archive::from_reader(&*buf).unwrap(), archive);
+
+        let limits = Limits {
+            max_total_bytes: 1024,
+            ..Limits::default()
+        };
+        let err = Archive::from_bytes_with_limits(&buf, limits).err().unwrap();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::LimitExceeded {
+                limit: "total size",
+                ..
+            }
+        ));
+    }
 }
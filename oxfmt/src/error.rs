@@ -0,0 +1,204 @@
+use std::{borrow::Cow, fmt, io, ops::RangeInclusive, str::Utf8Error};
+
+pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// A decoding failure, with the byte offset it happened at and the path of the field being read
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    /// Missing for errors raised through serde, until the reader fills it in
+    offset: Option<u64>,
+    /// Innermost segment first, as segments are added while the error is returned
+    path: Vec<PathSegment>,
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    UnexpectedEof,
+    BadHeader,
+    VersionMismatch {
+        found: u16,
+        supported: RangeInclusive<u16>,
+    },
+    UnsupportedFormat(u8),
+    UnsupportedFlags(u8),
+    /// The data is compressed with a method whose cargo feature isn't enabled
+    UnsupportedCompression(&'static str),
+    MissingChecksum,
+    LengthMismatch {
+        expected: u64,
+        found: u64,
+    },
+    ChecksumMismatch,
+    /// Bytes left over after the value was read
+    TrailingBytes(u64),
+    InvalidUtf8(Utf8Error),
+    InvalidDiscriminant {
+        ty: &'static str,
+        value: u8,
+    },
+    VarintOverflow,
+    SizeOverflow,
+    DuplicateKey,
+    /// Borrowed types can only be read from buffers
+    CannotBorrow,
+    /// A length or the depth went over one of the decoder's [`Limits`](crate::Limits)
+    LimitExceeded {
+        limit: &'static str,
+        max: u64,
+    },
+    Io(io::Error),
+    Custom(String),
+}
+
+#[derive(Debug)]
+enum PathSegment {
+    Field(Cow<'static, str>),
+    Index(usize),
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, offset: u64) -> Self {
+        Self {
+            kind,
+            offset: Some(offset),
+            path: Vec::new(),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn unlocated(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            offset: None,
+            path: Vec::new(),
+        }
+    }
+
+    pub fn custom(message: impl fmt::Display, offset: u64) -> Self {
+        Self::new(ErrorKind::Custom(message.to_string()), offset)
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset.unwrap_or(0)
+    }
+
+    /// The field that was being read, like `meta.config.mods[4].source`
+    pub fn path(&self) -> String {
+        let mut path = String::new();
+        for segment in self.path.iter().rev() {
+            match segment {
+                PathSegment::Field(name) if path.is_empty() => path.push_str(name),
+                PathSegment::Field(name) => {
+                    path.push('.');
+                    path.push_str(name);
+                }
+                PathSegment::Index(index) => path.push_str(&format!("[{index}]")),
+            }
+        }
+        path
+    }
+
+    /// Marks the error as happening inside the field `name`
+    pub fn in_field(mut self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.path.push(PathSegment::Field(name.into()));
+        self
+    }
+
+    /// Sets the offset if the error was raised without one
+    #[cfg(feature = "serde")]
+    pub(crate) fn locate(mut self, offset: u64) -> Self {
+        self.offset.get_or_insert(offset);
+        self
+    }
+
+    /// Marks the error as happening inside the item at `index` of a collection
+    pub fn at_index(mut self, index: usize) -> Self {
+        self.path.push(PathSegment::Index(index));
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if !self.path.is_empty() {
+            write!(f, " in `{}`", self.path())?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at byte {offset}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::InvalidUtf8(err) => Some(err),
+            ErrorKind::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::UnexpectedEof => write!(f, "unexpected end of data"),
+            ErrorKind::BadHeader => write!(f, "buffer does not start with header"),
+            ErrorKind::VersionMismatch { found, supported } if found < supported.start() => write!(
+                f,
+                "version {} is no longer supported, the oldest supported version is {}",
+                found,
+                supported.start()
+            ),
+            ErrorKind::VersionMismatch { found, supported } => write!(
+                f,
+                "version {} is newer than the latest supported version {}, update to read it",
+                found,
+                supported.end()
+            ),
+            ErrorKind::UnsupportedFormat(format) => {
+                write!(f, "unsupported format version {format}")
+            }
+            ErrorKind::UnsupportedFlags(flags) => write!(f, "unsupported flags {flags:#010b}"),
+            ErrorKind::UnsupportedCompression(name) => write!(
+                f,
+                "file is compressed with {name}, enable the `{name}` feature of oxfmt to read it"
+            ),
+            ErrorKind::MissingChecksum => {
+                write!(f, "file is truncated: checksum trailer is missing")
+            }
+            ErrorKind::LengthMismatch { expected, found } => write!(
+                f,
+                "file is corrupt or truncated: expected {expected} bytes but found {found}"
+            ),
+            ErrorKind::ChecksumMismatch => write!(f, "file is corrupt: checksum does not match"),
+            ErrorKind::TrailingBytes(len) => {
+                write!(f, "file is corrupt: {len} unexpected bytes after the end")
+            }
+            ErrorKind::InvalidUtf8(err) => write!(f, "invalid UTF-8: {err}"),
+            ErrorKind::InvalidDiscriminant { ty, value } => {
+                write!(f, "invalid {ty} discriminant: {value}")
+            }
+            ErrorKind::VarintOverflow => write!(f, "varint overflows u64"),
+            ErrorKind::SizeOverflow => write!(f, "value does not fit in usize"),
+            ErrorKind::DuplicateKey => write!(f, "duplicate map key or set item"),
+            ErrorKind::CannotBorrow => write!(
+                f,
+                "cannot borrow from a stream, read into an owned type instead"
+            ),
+            ErrorKind::LimitExceeded { limit, max } => {
+                write!(f, "{limit} is over the limit of {max}")
+            }
+            ErrorKind::Io(err) => write!(f, "{err}"),
+            ErrorKind::Custom(message) => write!(f, "{message}"),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+/// Encoding version written after the header and type version.
+///
+/// Format 1 stored the writer's pointer width in this byte instead, 0 for 32-bit and 1 for
+/// 64-bit, with every `usize` and length written at that width. Format 2 writes them as
+/// LEB128 varints so the output doesn't depend on the machine that produced it. Format 3 adds
+/// a byte of [`flags`] after the format version. Format 4 prefixes strings with their length
+/// instead of ending them with a NUL, so they can contain NUL and are read without scanning.
+pub const FORMAT_VERSION: u8 = 4;
+
+pub mod flags {
+    /// The buffer ends with a trailer holding the length and CRC32 of everything before it
+    pub const CHECKSUM: u8 = 1 << 0;
+    /// Everything after the flags, including any trailer, is a zstd frame. The trailer then
+    /// covers the uncompressed bytes.
+    pub const ZSTD: u8 = 1 << 1;
+    /// Like [`ZSTD`], with a raw deflate stream
+    pub const DEFLATE: u8 = 1 << 2;
+
+    pub(crate) const KNOWN: u8 = CHECKSUM | ZSTD | DEFLATE;
+}
+
+/// How the data after the header is compressed, see [`flags::ZSTD`] and [`flags::DEFLATE`].
+/// Each is behind the cargo feature of the same name.
+#[cfg(any(feature = "zstd", feature = "deflate"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    #[cfg(feature = "zstd")]
+    Zstd,
+    #[cfg(feature = "deflate")]
+    Deflate,
+}
+
+#[cfg(any(feature = "zstd", feature = "deflate"))]
+impl Compression {
+    pub(crate) fn flag(self) -> u8 {
+        match self {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => flags::ZSTD,
+            #[cfg(feature = "deflate")]
+            Compression::Deflate => flags::DEFLATE,
+        }
+    }
+
+    pub(crate) fn from_flags(flags: u8) -> Option<Self> {
+        #[cfg(feature = "zstd")]
+        if flags & flags::ZSTD != 0 {
+            return Some(Compression::Zstd);
+        }
+        #[cfg(feature = "deflate")]
+        if flags & flags::DEFLATE != 0 {
+            return Some(Compression::Deflate);
+        }
+        None
+    }
+}
+
+/// Length of the checksum trailer: a `u64` length and a `u32` CRC32
+pub(crate) const TRAILER_LEN: usize = 12;
+
+/// Longest possible LEB128 encoding of a `u64`
+pub(crate) const MAX_VARINT_LEN: usize = 10;
+
+/// A LEB128 encoded `u64`, kept on the stack as it's written for every length
+pub(crate) struct Varint {
+    buf: [u8; MAX_VARINT_LEN],
+    len: u8,
+}
+
+impl std::ops::Deref for Varint {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf[..self.len as usize]
+    }
+}
+
+pub(crate) fn varint(mut value: u64) -> Varint {
+    let mut varint = Varint {
+        buf: [0; MAX_VARINT_LEN],
+        len: 0,
+    };
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = value == 0;
+        varint.buf[varint.len as usize] = if done { byte } else { byte | 0x80 };
+        varint.len += 1;
+        if done {
+            return varint;
+        }
+    }
+}
+
+/// Number of bytes `varint` encodes `value` in
+pub(crate) fn varint_len(value: u64) -> usize {
+    (64 - value.leading_zeros() as usize).max(1).div_ceil(7)
+}
@@ -1,10 +1,28 @@
 mod builder;
 mod deserialize;
-mod macros;
+mod error;
+mod format;
+mod limits;
+mod schema;
+#[cfg(feature = "serde")]
+pub mod serde;
+pub mod tagged;
 mod types;
 
+// Lets the derives, which refer to `oxfmt::`, be used in this crate
+extern crate self as oxfmt;
+
 pub use builder::BinaryBuilder;
 pub use deserialize::Deserialize;
-pub use macros::macros_;
-pub use oxfmt_derive::{Deserializable, Serializable};
-pub use types::{Deserializable, Field, Serializable, Structure};
+pub use error::{Error, ErrorKind};
+#[cfg(any(feature = "zstd", feature = "deflate"))]
+pub use format::Compression;
+pub use format::{FORMAT_VERSION, flags};
+pub use limits::Limits;
+pub use oxfmt_derive::{Describe, Deserializable, Serializable};
+pub use schema::{Describe, Schema, Value};
+#[doc(hidden)]
+pub use anyhow;
+#[doc(hidden)]
+pub use schema::describe_named;
+pub use types::{Deserializable, Field, Serializable, Structure, Versioned};
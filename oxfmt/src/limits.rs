@@ -0,0 +1,26 @@
+/// Bounds on what [`Deserialize`](crate::Deserialize) accepts, for reading untrusted data.
+/// Each is checked against the lengths in the data before anything is allocated for them.
+///
+/// The default only limits the depth, which keeps recursive types from overflowing the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Most items in a collection or entries in a map
+    pub max_len: usize,
+    /// Longest string, in bytes
+    pub max_string_len: usize,
+    /// Most bytes read in total, counting the header and after any decompression
+    pub max_total_bytes: u64,
+    /// Deepest nesting of structs, enums and collections
+    pub max_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_len: usize::MAX,
+            max_string_len: usize::MAX,
+            max_total_bytes: u64::MAX,
+            max_depth: 128,
+        }
+    }
+}
@@ -0,0 +1,309 @@
+mod cli;
+
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow};
+use clap::Parser;
+use cli::{OxfmtCli, Subcommands};
+use oxfmt::{Limits, Schema, Value, Versioned};
+
+/// Bytes shown before a blob is cut short in text output
+const BYTES_PREVIEW: usize = 16;
+
+fn main() -> Result<()> {
+    let cli = OxfmtCli::parse();
+
+    match cli.subcommand {
+        Subcommands::Dump {
+            file,
+            schemas,
+            json,
+            max_len,
+            max_string_len,
+            max_total_bytes,
+            max_depth,
+        } => {
+            // The file may come from anywhere, so it's read within limits that can be raised
+            let default = Limits::default();
+            let limits = Limits {
+                max_len: max_len.unwrap_or(default.max_len),
+                max_string_len: max_string_len.unwrap_or(default.max_string_len),
+                max_total_bytes: max_total_bytes.unwrap_or(default.max_total_bytes),
+                max_depth: max_depth.unwrap_or(default.max_depth),
+            };
+            dump(&file, &schemas, json, limits)
+        }
+    }
+}
+
+/// Decodes `file` with whichever of the given schemas has its header and prints it
+fn dump(file: &Path, schemas: &[PathBuf], json: bool, limits: Limits) -> Result<()> {
+    let schemas = schemas
+        .iter()
+        .map(|path| {
+            let buf = fs::read(path).with_context(|| format!("cannot read {}", path.display()))?;
+            Schema::from_bytes(&buf)
+                .with_context(|| format!("{} is not an oxfmt schema", path.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let buf = fs::read(file).with_context(|| format!("cannot read {}", file.display()))?;
+    // Prefer the longest header, in case one schema's header starts with another's
+    let schema = schemas
+        .iter()
+        .filter(|schema| buf.starts_with(&schema.header))
+        .max_by_key(|schema| schema.header.len())
+        .ok_or_else(|| anyhow!("none of the schemas match the header of {}", file.display()))?;
+    let value = schema.read_with_limits(&buf, limits)?;
+
+    let mut out = String::new();
+    if json {
+        write_json(&mut out, &value);
+        out.push('\n');
+    } else {
+        match &value {
+            Value::Struct(fields) => {
+                for (name, field) in fields {
+                    write_text(&mut out, name, field, 0);
+                }
+            }
+            value => writeln!(out, "{}", inline(value))?,
+        }
+    }
+    print!("{out}");
+    Ok(())
+}
+
+/// Writes a value as an indented tree, one line per scalar
+fn write_text(out: &mut String, label: &str, value: &Value, depth: usize) {
+    let pad = "  ".repeat(depth);
+    match value {
+        Value::Option(Some(inner)) => write_text(out, label, inner, depth),
+        Value::Struct(fields) | Value::Variant(_, fields) if !fields.is_empty() => {
+            match value {
+                Value::Variant(name, _) => _ = writeln!(out, "{pad}{label}: {name}"),
+                _ => _ = writeln!(out, "{pad}{label}:"),
+            }
+            for (name, field) in fields {
+                write_text(out, name, field, depth + 1);
+            }
+        }
+        Value::Vector(items) if !items.is_empty() => {
+            _ = writeln!(out, "{pad}{label}:");
+            for (i, item) in items.iter().enumerate() {
+                write_text(out, &format!("[{i}]"), item, depth + 1);
+            }
+        }
+        Value::Map(entries) if !entries.is_empty() => {
+            _ = writeln!(out, "{pad}{label}:");
+            for (key, value) in entries {
+                write_text(out, &format!("[{}]", inline(key)), value, depth + 1);
+            }
+        }
+        value => _ = writeln!(out, "{pad}{label}: {}", inline(value)),
+    }
+}
+
+/// Formats a value on a single line, for scalars and map keys
+fn inline(value: &Value) -> String {
+    match value {
+        Value::String(string) => format!("{string:?}"),
+        Value::Unsigned(number) => number.to_string(),
+        Value::Signed(number) => number.to_string(),
+        Value::Float(number) => number.to_string(),
+        Value::Bool(bool) => bool.to_string(),
+        Value::Bytes(bytes) => {
+            let preview: Vec<_> = bytes
+                .iter()
+                .take(BYTES_PREVIEW)
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            match bytes.len() {
+                0 => String::from("<0 bytes>"),
+                len if len > BYTES_PREVIEW => format!("<{len} bytes: {} ...>", preview.join(" ")),
+                len => format!("<{len} bytes: {}>", preview.join(" ")),
+            }
+        }
+        Value::Option(None) => String::from("none"),
+        Value::Option(Some(inner)) => inline(inner),
+        Value::Variant(name, fields) if fields.is_empty() => name.clone(),
+        Value::Struct(fields) | Value::Variant(_, fields) => {
+            let fields: Vec<_> = fields
+                .iter()
+                .map(|(name, field)| format!("{name}: {}", inline(field)))
+                .collect();
+            match value {
+                Value::Variant(name, _) => format!("{name} {{ {} }}", fields.join(", ")),
+                _ => format!("{{ {} }}", fields.join(", ")),
+            }
+        }
+        Value::Vector(items) => {
+            let items: Vec<_> = items.iter().map(inline).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Map(entries) => {
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", inline(key), inline(value)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+    }
+}
+
+/// Writes a value as JSON: bytes become hex strings, maps with string keys become objects and
+/// enum variants with fields become an object keyed by the variant name
+fn write_json(out: &mut String, value: &Value) {
+    match value {
+        Value::String(string) => write_json_string(out, string),
+        Value::Unsigned(number) => _ = write!(out, "{number}"),
+        Value::Signed(number) => _ = write!(out, "{number}"),
+        Value::Float(number) if number.is_finite() => _ = write!(out, "{number}"),
+        Value::Float(_) | Value::Option(None) => out.push_str("null"),
+        Value::Bool(bool) => _ = write!(out, "{bool}"),
+        Value::Bytes(bytes) => {
+            out.push('"');
+            for byte in bytes {
+                _ = write!(out, "{byte:02x}");
+            }
+            out.push('"');
+        }
+        Value::Option(Some(inner)) => write_json(out, inner),
+        Value::Struct(fields) => write_json_object(out, fields),
+        Value::Variant(name, fields) if fields.is_empty() => write_json_string(out, name),
+        Value::Variant(name, fields) => {
+            out.push('{');
+            write_json_string(out, name);
+            out.push(':');
+            write_json_object(out, fields);
+            out.push('}');
+        }
+        Value::Vector(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(out, item);
+            }
+            out.push(']');
+        }
+        Value::Map(entries)
+            if entries
+                .iter()
+                .all(|(key, _)| matches!(key, Value::String(_))) =>
+        {
+            out.push('{');
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(out, key);
+                out.push(':');
+                write_json(out, value);
+            }
+            out.push('}');
+        }
+        Value::Map(entries) => {
+            out.push('[');
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str("{\"key\":");
+                write_json(out, key);
+                out.push_str(",\"value\":");
+                write_json(out, value);
+                out.push('}');
+            }
+            out.push(']');
+        }
+    }
+}
+
+fn write_json_object(out: &mut String, fields: &[(String, Value)]) {
+    out.push('{');
+    for (i, (name, value)) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_json_string(out, name);
+        out.push(':');
+        write_json(out, value);
+    }
+    out.push('}');
+}
+
+fn write_json_string(out: &mut String, string: &str) {
+    out.push('"');
+    for char in string.chars() {
+        match char {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            char if char < ' ' => _ = write!(out, "\\u{:04x}", char as u32),
+            char => out.push(char),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Value {
+        Value::Struct(vec![
+            (
+                String::from("name"),
+                Value::String(String::from("a \"pack\"\n")),
+            ),
+            (
+                String::from("loader"),
+                Value::Variant(String::from("Fabric"), Vec::new()),
+            ),
+            (
+                String::from("mods"),
+                Value::Vector(vec![Value::Struct(vec![(
+                    String::from("id"),
+                    Value::Option(Some(Box::new(Value::Unsigned(7)))),
+                )])]),
+            ),
+            (String::from("include"), Value::Bytes(vec![0x50, 0x4b])),
+        ])
+    }
+
+    #[test]
+    fn text_is_an_indented_tree() {
+        let Value::Struct(fields) = sample() else {
+            unreachable!()
+        };
+        let mut out = String::new();
+        for (name, field) in &fields {
+            write_text(&mut out, name, field, 0);
+        }
+        assert_eq!(
+            out,
+            "name: \"a \\\"pack\\\"\\n\"\n\
+             loader: Fabric\n\
+             mods:\n  [0]:\n    id: 7\n\
+             include: <2 bytes: 50 4b>\n"
+        );
+    }
+
+    #[test]
+    fn json_is_escaped() {
+        let mut out = String::new();
+        write_json(&mut out, &sample());
+        assert_eq!(
+            out,
+            r#"{"name":"a \"pack\"\n","loader":"Fabric","mods":[{"id":7}],"include":"504b"}"#
+        );
+    }
+}
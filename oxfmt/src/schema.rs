@@ -0,0 +1,493 @@
+use std::{
+    any,
+    borrow::Cow,
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+};
+
+use crate::{Deserialize, Error, ErrorKind, Field, Limits, Structure, Versioned, tagged};
+use oxfmt_derive::{Deserializable, Serializable};
+
+/// The layout a type is written with, down to its nested fields.
+///
+/// Derive it alongside `Serializable` with `#[derive(Describe)]`, a [`Schema`] built from it
+/// can then read the type's files without the type itself.
+pub trait Describe {
+    fn describe() -> Field;
+}
+
+thread_local! {
+    /// The derived types whose layout is being described, innermost last
+    static DESCRIBING: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Describes a derived type, or refers to it by name when it's already being described so
+/// recursive types don't describe themselves forever
+pub fn describe_named<T: ?Sized>(name: &str, describe: impl FnOnce() -> Field) -> Field {
+    struct Guard;
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            DESCRIBING.with_borrow_mut(|types| types.pop());
+        }
+    }
+
+    let ty = any::type_name::<T>();
+    if DESCRIBING.with_borrow(|types| types.contains(&ty)) {
+        return Field::Recursive(name.to_string());
+    }
+    DESCRIBING.with_borrow_mut(|types| types.push(ty));
+    let _guard = Guard;
+    describe()
+}
+
+macro_rules! impl_describe {
+    ($($ty:ty => $field:ident),* $(,)?) => {$(
+        impl Describe for $ty {
+            fn describe() -> Field {
+                Field::$field
+            }
+        }
+    )*};
+}
+
+impl_describe! {
+    str => String,
+    String => String,
+    u8 => U8,
+    u16 => U16,
+    u32 => U32,
+    u64 => U64,
+    u128 => U128,
+    i8 => I8,
+    i16 => I16,
+    i32 => I32,
+    i64 => I64,
+    i128 => I128,
+    f32 => F32,
+    f64 => F64,
+    bool => Bool,
+    usize => Usize,
+    [u8] => Bytes,
+}
+
+impl<T: Describe + ?Sized> Describe for &T {
+    fn describe() -> Field {
+        T::describe()
+    }
+}
+
+impl<T: Describe + ?Sized> Describe for Box<T> {
+    fn describe() -> Field {
+        T::describe()
+    }
+}
+
+impl<T: Describe + ToOwned + ?Sized> Describe for Cow<'_, T> {
+    fn describe() -> Field {
+        T::describe()
+    }
+}
+
+impl<T: Describe> Describe for Vec<T> {
+    fn describe() -> Field {
+        Field::vector(T::describe())
+    }
+}
+
+impl<T: Describe, const N: usize> Describe for [T; N] {
+    fn describe() -> Field {
+        Field::Tuple(vec![T::describe(); N])
+    }
+}
+
+impl<T: Describe> Describe for Option<T> {
+    fn describe() -> Field {
+        Field::option(T::describe())
+    }
+}
+
+impl<K: Describe, V: Describe, S> Describe for HashMap<K, V, S> {
+    fn describe() -> Field {
+        Field::map(K::describe(), V::describe())
+    }
+}
+
+impl<K: Describe, V: Describe> Describe for BTreeMap<K, V> {
+    fn describe() -> Field {
+        Field::map(K::describe(), V::describe())
+    }
+}
+
+impl<T: Describe, S> Describe for HashSet<T, S> {
+    fn describe() -> Field {
+        Field::set(T::describe())
+    }
+}
+
+impl<T: Describe> Describe for BTreeSet<T> {
+    fn describe() -> Field {
+        Field::set(T::describe())
+    }
+}
+
+macro_rules! impl_describe_tuple {
+    ($(($($item:ident),+)),* $(,)?) => {$(
+        impl<$($item: Describe),+> Describe for ($($item,)+) {
+            fn describe() -> Field {
+                Field::Tuple(vec![$($item::describe()),+])
+            }
+        }
+    )*};
+}
+
+impl_describe_tuple! {
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+}
+
+/// The layout of a versioned type's files, saved so they can be inspected without the type
+#[derive(Debug, Clone, PartialEq, Serializable, Deserializable)]
+#[oxfmt(header = "oxfmt-schema", version = 1)]
+pub struct Schema {
+    pub header: Box<[u8]>,
+    /// Only files of this version can be read, older layouts aren't described
+    pub version: u16,
+    pub root: Field,
+}
+
+impl Schema {
+    pub fn of<'a, T: Versioned<'a> + Describe>() -> Self {
+        Self {
+            header: T::HEADER.into(),
+            version: T::VERSION,
+            root: T::describe(),
+        }
+    }
+
+    /// Reads a whole file written with this schema's header and version
+    pub fn read(&self, buf: &[u8]) -> Result<Value, Error> {
+        self.read_with_limits(buf, Limits::default())
+    }
+
+    /// Like [`read`](Schema::read), for files from untrusted sources
+    pub fn read_with_limits(&self, buf: &[u8], limits: Limits) -> Result<Value, Error> {
+        let versions = self.version..=self.version;
+        let mut deserialize = Deserialize::new_with_limits(buf, &self.header, versions, limits)?;
+        let value = Value::read(&mut deserialize, &self.root)?;
+        deserialize.finish()?;
+        Ok(value)
+    }
+}
+
+/// A value read by following a [`Field`] instead of a type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Unsigned(u128),
+    Signed(i128),
+    Float(f64),
+    Bool(bool),
+    Bytes(Vec<u8>),
+    Struct(Vec<(String, Value)>),
+    /// Vectors, sets and tuples
+    Vector(Vec<Value>),
+    Option(Option<Box<Value>>),
+    Map(Vec<(Value, Value)>),
+    /// An enum variant and its fields, which are empty for fieldless variants
+    Variant(String, Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn read(deserialize: &mut Deserialize, field: &Field) -> Result<Self, Error> {
+        Self::read_in(deserialize, field, &mut Vec::new())
+    }
+
+    /// Reads a value inside `scopes`, the structs and enums it's nested in that recursive
+    /// fields refer to
+    fn read_in<'a>(
+        deserialize: &mut Deserialize,
+        field: &'a Field,
+        scopes: &mut Vec<&'a Field>,
+    ) -> Result<Self, Error> {
+        if scope_name(field).is_none() {
+            return Self::read_field(deserialize, field, scopes);
+        }
+        scopes.push(field);
+        let value = Self::read_field(deserialize, field, scopes);
+        scopes.pop();
+        value
+    }
+
+    fn read_field<'a>(
+        deserialize: &mut Deserialize,
+        field: &'a Field,
+        scopes: &mut Vec<&'a Field>,
+    ) -> Result<Self, Error> {
+        Ok(match field {
+            Field::String => Value::String(deserialize.read_string()?),
+            Field::U8 => Value::Unsigned(deserialize.read_u8()?.into()),
+            Field::U16 => Value::Unsigned(deserialize.read_u16()?.into()),
+            Field::U32 => Value::Unsigned(deserialize.read_u32()?.into()),
+            Field::U64 => Value::Unsigned(deserialize.read_u64()?.into()),
+            Field::U128 => Value::Unsigned(deserialize.read_u128()?),
+            Field::Usize => Value::Unsigned(deserialize.read_usize()? as u128),
+            Field::I8 => Value::Signed(deserialize.read_i8()?.into()),
+            Field::I16 => Value::Signed(deserialize.read_i16()?.into()),
+            Field::I32 => Value::Signed(deserialize.read_i32()?.into()),
+            Field::I64 => Value::Signed(deserialize.read_i64()?.into()),
+            Field::I128 => Value::Signed(deserialize.read_i128()?),
+            Field::F32 => Value::Float(deserialize.read_f32()?.into()),
+            Field::F64 => Value::Float(deserialize.read_f64()?),
+            Field::Bool => Value::Bool(deserialize.read_bool()?),
+            Field::Bytes => Value::Bytes(deserialize.read_cow_bytes()?.into_owned()),
+            Field::Struct(structure) => Value::Struct(read_fields(deserialize, structure, scopes)?),
+            Field::Tuple(fields) => deserialize.nested(|deserialize| {
+                let items = fields.iter().enumerate().map(|(i, field)| {
+                    Value::read_in(deserialize, field, scopes)
+                        .map_err(|err| err.in_field(i.to_string()))
+                });
+                Ok(Value::Vector(items.collect::<Result<_, _>>()?))
+            })?,
+            Field::Vector(item) | Field::Set(item) => deserialize.nested(|deserialize| {
+                let len = deserialize.read_len()?;
+                let items = (0..len).map(|i| {
+                    Value::read_in(deserialize, item, scopes).map_err(|err| err.at_index(i))
+                });
+                Ok(Value::Vector(items.collect::<Result<_, _>>()?))
+            })?,
+            Field::Map(key, value) => deserialize.nested(|deserialize| {
+                let len = deserialize.read_len()?;
+                let entries = (0..len).map(|i| {
+                    let key =
+                        Value::read_in(deserialize, key, scopes).map_err(|err| err.at_index(i))?;
+                    let value = Value::read_in(deserialize, value, scopes)
+                        .map_err(|err| err.at_index(i))?;
+                    Ok((key, value))
+                });
+                Ok(Value::Map(entries.collect::<Result<_, _>>()?))
+            })?,
+            Field::Tagged { fields, .. } => deserialize.nested(|deserialize| {
+                let mut values = Vec::new();
+                tagged::read_fields(deserialize, |deserialize, tag| {
+                    let Some((_, name, field)) = fields.iter().find(|(t, ..)| u64::from(*t) == tag)
+                    else {
+                        return Ok(false);
+                    };
+                    let value = Value::read_in(deserialize, field, scopes)
+                        .map_err(|err| err.in_field(name.clone()))?;
+                    values.push((name.clone(), value));
+                    Ok(true)
+                })?;
+                Ok(Value::Struct(values))
+            })?,
+            Field::Option(inner) => {
+                let offset = deserialize.offset();
+                match deserialize.read_u8()? {
+                    0 => Value::Option(None),
+                    1 => Value::Option(Some(Box::new(Value::read_in(deserialize, inner, scopes)?))),
+                    value => return Err(invalid_discriminant("Option", value, offset)),
+                }
+            }
+            Field::Enum { variants, .. } => {
+                let offset = deserialize.offset();
+                let tag = deserialize.read_u8()?;
+                let Some(variant) = variants.get(tag as usize) else {
+                    return Err(invalid_discriminant("enum", tag, offset));
+                };
+                Value::Variant(
+                    variant.name.clone(),
+                    read_fields(deserialize, variant, scopes)?,
+                )
+            }
+            Field::Repr { variants, .. } => {
+                let offset = deserialize.offset();
+                let value = deserialize.read_u8()?;
+                match variants
+                    .iter()
+                    .find(|(_, discriminant)| *discriminant == value)
+                {
+                    Some((name, _)) => Value::Variant(name.clone(), Vec::new()),
+                    None => return Err(invalid_discriminant("enum", value, offset)),
+                }
+            }
+            Field::Recursive(name) => {
+                let Some(scope) = scopes
+                    .iter()
+                    .rev()
+                    .find(|scope| scope_name(scope) == Some(name))
+                else {
+                    let msg = format!("schema refers to `{name}` outside of it");
+                    return Err(Error::new(ErrorKind::Custom(msg), deserialize.offset()));
+                };
+                Value::read_in(deserialize, scope, scopes)?
+            }
+        })
+    }
+}
+
+/// The name recursive fields refer to a struct or enum by
+fn scope_name(field: &Field) -> Option<&String> {
+    match field {
+        Field::Struct(structure) => Some(&structure.name),
+        Field::Enum { name, .. } | Field::Tagged { name, .. } => Some(name),
+        _ => None,
+    }
+}
+
+fn read_fields<'a>(
+    deserialize: &mut Deserialize,
+    structure: &'a Structure,
+    scopes: &mut Vec<&'a Field>,
+) -> Result<Vec<(String, Value)>, Error> {
+    deserialize.nested(|deserialize| {
+        structure
+            .fields
+            .iter()
+            .map(|(name, field)| {
+                let value = Value::read_in(deserialize, field, scopes)
+                    .map_err(|err| err.in_field(name.clone()))?;
+                Ok((name.clone(), value))
+            })
+            .collect()
+    })
+}
+
+fn invalid_discriminant(ty: &'static str, value: u8, offset: u64) -> Error {
+    Error::new(ErrorKind::InvalidDiscriminant { ty, value }, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::{BinaryBuilder, Describe, Deserializable, Serializable};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Serializable, Deserializable, Describe)]
+    #[repr(u8)]
+    enum Loader {
+        Fabric = 0,
+        Quilt = 3,
+    }
+
+    #[derive(Debug, PartialEq, Serializable, Deserializable, Describe)]
+    enum Source {
+        Local,
+        Url(String, u32),
+    }
+
+    #[derive(Debug, PartialEq, Serializable, Deserializable, Describe)]
+    #[oxfmt(header = "dump", version = 2, checksum)]
+    struct Dump {
+        loader: Loader,
+        sources: Vec<Source>,
+        tags: BTreeMap<String, (bool, i8)>,
+        note: Option<String>,
+    }
+
+    #[test]
+    fn schemas_read_without_the_type() {
+        let schema = Schema::of::<Dump>();
+        let schema = Schema::from_bytes(&schema.serialize().unwrap()).unwrap();
+        assert_eq!(schema, Schema::of::<Dump>());
+
+        let dump = Dump {
+            loader: Loader::Quilt,
+            sources: vec![Source::Local, Source::Url(String::from("a"), 2)],
+            tags: BTreeMap::from([(String::from("x"), (true, -1))]),
+            note: None,
+        };
+        let buf = dump.serialize().unwrap();
+        let variant = |name: &str, fields| Value::Variant(String::from(name), fields);
+        assert_eq!(
+            schema.read(&buf).unwrap(),
+            Value::Struct(vec![
+                (String::from("loader"), variant("Quilt", vec![])),
+                (
+                    String::from("sources"),
+                    Value::Vector(vec![
+                        variant("Local", vec![]),
+                        variant(
+                            "Url",
+                            vec![
+                                (String::from("0"), Value::String(String::from("a"))),
+                                (String::from("1"), Value::Unsigned(2)),
+                            ]
+                        ),
+                    ])
+                ),
+                (
+                    String::from("tags"),
+                    Value::Map(vec![(
+                        Value::String(String::from("x")),
+                        Value::Vector(vec![Value::Bool(true), Value::Signed(-1)])
+                    )])
+                ),
+                (String::from("note"), Value::Option(None)),
+            ])
+        );
+
+        // The loader is the first byte after the header, version, format and flags
+        let mut corrupt = buf.to_vec();
+        corrupt["dump".len() + 4] = 1;
+        let err = Schema::of::<Dump>().read(&corrupt).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::ChecksumMismatch));
+
+        let limits = Limits {
+            max_len: 1,
+            ..Limits::default()
+        };
+        let err = schema.read_with_limits(&buf, limits).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::LimitExceeded { .. }));
+    }
+
+    #[derive(Debug, PartialEq, Serializable, Deserializable, Describe)]
+    struct Tree {
+        name: String,
+        children: Vec<Tree>,
+    }
+
+    #[test]
+    fn recursive_types_refer_to_themselves() {
+        let root = Tree::describe();
+        let Field::Struct(structure) = &root else {
+            panic!("expected a struct, got {root:?}");
+        };
+        assert_eq!(
+            structure.fields[1],
+            (
+                String::from("children"),
+                Field::vector(Field::Recursive(String::from("Tree")))
+            )
+        );
+
+        let leaf = |name: &str| Tree {
+            name: String::from(name),
+            children: Vec::new(),
+        };
+        let tree = Tree {
+            name: String::from("a"),
+            children: vec![Tree {
+                name: String::from("b"),
+                children: vec![leaf("c")],
+            }],
+        };
+        let buf = BinaryBuilder::new(b"tree", 1).add(&tree).unwrap().build();
+        let schema = Schema {
+            header: Box::from(&b"tree"[..]),
+            version: 1,
+            root,
+        };
+        let node = |name: &str, children| {
+            Value::Struct(vec![
+                (String::from("name"), Value::String(String::from(name))),
+                (String::from("children"), Value::Vector(children)),
+            ])
+        };
+        assert_eq!(
+            schema.read(&buf).unwrap(),
+            node("a", vec![node("b", vec![node("c", vec![])])])
+        );
+    }
+}
@@ -0,0 +1,785 @@
+//! Reads and writes types through their serde derives, using the same layout as the oxfmt
+//! derives: fields in order without names, enums as their `u8` variant index followed by the
+//! variant's fields. Unlike the derives, `usize` goes through serde as a fixed width `u64`,
+//! fieldless enums are written as their variant index rather than their discriminant, and hash
+//! maps are written in iteration order.
+
+use std::{borrow::Cow, fmt::Display, io::Write};
+
+use ::serde::{
+    de::{self, DeserializeSeed, IntoDeserializer, Visitor},
+    ser::{self, Serialize},
+};
+
+use crate::{Deserializable, Deserialize, Error, ErrorKind, Serializable, format::varint};
+
+/// Wraps a serde type so it can be added to a [`BinaryBuilder`](crate::BinaryBuilder) or used as
+/// a field of a type deriving the oxfmt traits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Serde<T>(pub T);
+
+impl<T: Serialize> Serializable for Serde<T> {
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> anyhow::Result<()> {
+        Ok(to_writer(writer, &self.0)?)
+    }
+}
+
+impl<'a, T: de::Deserialize<'a>> Deserializable<'a> for Serde<T> {
+    fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+        deserialize.read_serde().map(Serde)
+    }
+}
+
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(writer: W, value: &T) -> Result<(), Error> {
+    let mut serializer = Serializer::new(writer);
+    value
+        .serialize(&mut serializer)
+        .map_err(|err| err.locate(serializer.written))
+}
+
+impl<'a> Deserialize<'a> {
+    /// Reads a type through its serde `Deserialize` impl
+    pub fn read_serde<T: de::Deserialize<'a>>(&mut self) -> Result<T, Error> {
+        T::deserialize(&mut *self).map_err(|err| err.locate(self.offset()))
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::unlocated(ErrorKind::Custom(msg.to_string()))
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::unlocated(ErrorKind::Custom(msg.to_string()))
+    }
+}
+
+fn unsupported(message: &str) -> Error {
+    Error::unlocated(ErrorKind::Custom(message.to_string()))
+}
+
+pub struct Serializer<W> {
+    writer: W,
+    /// Bytes written so far, the offset of any error
+    written: u64,
+}
+
+impl<W: Write> Serializer<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, written: 0 }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<(), Error> {
+        self.writer
+            .write_all(buf)
+            .map_err(|err| Error::new(ErrorKind::Io(err), self.written))?;
+        self.written += buf.len() as u64;
+        Ok(())
+    }
+
+    fn write_len(&mut self, len: Option<usize>) -> Result<(), Error> {
+        let len = len.ok_or_else(|| unsupported("sequences and maps need a known length"))?;
+        self.write(&varint(len as u64))
+    }
+
+    fn write_tag(&mut self, variant_index: u32) -> Result<(), Error> {
+        let tag = u8::try_from(variant_index)
+            .map_err(|_| unsupported("enums with more than 256 variants are not supported"))?;
+        self.write(&[tag])
+    }
+}
+
+impl<W: Write> ser::Serializer for &mut Serializer<W> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.write(&[v as u8])
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.write(&v.to_le_bytes())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.write(&v.to_le_bytes())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.write(&v.to_le_bytes())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.write(&v.to_le_bytes())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<(), Error> {
+        self.write(&v.to_le_bytes())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.write(&v.to_le_bytes())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.write(&v.to_le_bytes())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.write(&v.to_le_bytes())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.write(&v.to_le_bytes())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<(), Error> {
+        self.write(&v.to_le_bytes())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.write(&v.to_le_bytes())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        self.write(&v.to_le_bytes())
+    }
+
+    // Chars are written as their code point
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.write(&(v as u32).to_le_bytes())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        self.write(&varint(v.len() as u64))?;
+        self.write(v)
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.write(&[0])
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        self.write(&[1])?;
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), Error> {
+        self.write_tag(variant_index)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.write_tag(variant_index)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self, Error> {
+        self.write_len(len)?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, Error> {
+        self.write_tag(variant_index)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self, Error> {
+        self.write_len(len)?;
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, Error> {
+        self.write_tag(variant_index)?;
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+// Compound values are their items in order, any length was written when they were started
+macro_rules! impl_compound {
+    ($($trait:ident::$method:ident),* $(,)?) => {$(
+        impl<W: Write> ser::$trait for &mut Serializer<W> {
+            type Ok = ();
+            type Error = Error;
+
+            fn $method<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+                value.serialize(&mut **self)
+            }
+
+            fn end(self) -> Result<(), Error> {
+                Ok(())
+            }
+        }
+    )*};
+}
+
+impl_compound! {
+    SerializeSeq::serialize_element,
+    SerializeTuple::serialize_element,
+    SerializeTupleStruct::serialize_field,
+    SerializeTupleVariant::serialize_field,
+}
+
+impl<W: Write> ser::SerializeMap for &mut Serializer<W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+// Fields are found by position, so one that's skipped would shift every field after it
+macro_rules! impl_struct {
+    ($($trait:ident),* $(,)?) => {$(
+        impl<W: Write> ser::$trait for &mut Serializer<W> {
+            type Ok = ();
+            type Error = Error;
+
+            fn serialize_field<T: Serialize + ?Sized>(
+                &mut self,
+                _key: &'static str,
+                value: &T,
+            ) -> Result<(), Error> {
+                value.serialize(&mut **self)
+            }
+
+            fn skip_field(&mut self, key: &'static str) -> Result<(), Error> {
+                Err(unsupported(&format!("field `{key}` cannot be skipped")))
+            }
+
+            fn end(self) -> Result<(), Error> {
+                Ok(())
+            }
+        }
+    )*};
+}
+
+impl_struct! {
+    SerializeStruct,
+    SerializeStructVariant,
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserialize<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(unsupported(
+            "oxfmt data doesn't describe itself, the type being read has to",
+        ))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_bool(self.read_bool()?)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i8(self.read_i8()?)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i16(self.read_i16()?)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i32(self.read_i32()?)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i64(self.read_i64()?)
+    }
+
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_i128(self.read_i128()?)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u8(self.read_u8()?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u16(self.read_u16()?)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u32(self.read_u32()?)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u64(self.read_u64()?)
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_u128(self.read_u128()?)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f32(self.read_f32()?)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f64(self.read_f64()?)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let offset = self.offset();
+        let value = self.read_u32()?;
+        let char = char::from_u32(value)
+            .ok_or_else(|| Error::custom(format!("invalid char {value:#x}"), offset))?;
+        visitor.visit_char(char)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.read_cow_str()? {
+            Cow::Borrowed(str) => visitor.visit_borrowed_str(str),
+            Cow::Owned(string) => visitor.visit_string(string),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.read_cow_bytes()? {
+            Cow::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+            Cow::Owned(bytes) => visitor.visit_byte_buf(bytes),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let offset = self.offset();
+        match self.read_u8()? {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            value => Err(Error::new(
+                ErrorKind::InvalidDiscriminant {
+                    ty: "Option",
+                    value,
+                },
+                offset,
+            )),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.nested(|deserialize| {
+            let len = deserialize.read_len()?;
+            visitor.visit_seq(Items::new(deserialize, len, None))
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.nested(|deserialize| visitor.visit_seq(Items::new(deserialize, len, None)))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.nested(|deserialize| visitor.visit_seq(Items::new(deserialize, len, None)))
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.nested(|deserialize| {
+            let len = deserialize.read_len()?;
+            visitor.visit_map(Items::new(deserialize, len, None))
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.nested(|deserialize| {
+            visitor.visit_seq(Items::new(deserialize, fields.len(), Some(fields)))
+        })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let offset = self.offset();
+        let tag = self.read_u8()?;
+        if tag as usize >= variants.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidDiscriminant {
+                    ty: name,
+                    value: tag,
+                },
+                offset,
+            ));
+        }
+        self.nested(|deserialize| visitor.visit_enum(Variant { deserialize, tag }))
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+/// The items of a sequence, tuple or map, or the fields of a struct when `fields` is set
+struct Items<'d, 'de> {
+    deserialize: &'d mut Deserialize<'de>,
+    len: usize,
+    index: usize,
+    fields: Option<&'static [&'static str]>,
+}
+
+impl<'d, 'de> Items<'d, 'de> {
+    fn new(
+        deserialize: &'d mut Deserialize<'de>,
+        len: usize,
+        fields: Option<&'static [&'static str]>,
+    ) -> Self {
+        Self {
+            deserialize,
+            len,
+            index: 0,
+            fields,
+        }
+    }
+
+    /// Reads the item at `index`, adding its field name or index to the path of any error
+    fn read<T>(
+        &mut self,
+        index: usize,
+        read: impl FnOnce(&mut Deserialize<'de>) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let offset = self.deserialize.offset();
+        read(self.deserialize).map_err(|err| {
+            let err = match self.fields {
+                Some(fields) => err.in_field(fields[index]),
+                None => err.at_index(index),
+            };
+            err.locate(offset)
+        })
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for Items<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if self.index == self.len {
+            return Ok(None);
+        }
+        self.index += 1;
+        self.read(self.index - 1, |deserialize| seed.deserialize(deserialize))
+            .map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len - self.index)
+    }
+}
+
+// Each key and value pair is one entry, so the index only moves on once the value is read
+impl<'de> de::MapAccess<'de> for Items<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        if self.index == self.len {
+            return Ok(None);
+        }
+        self.read(self.index, |deserialize| seed.deserialize(deserialize))
+            .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        self.index += 1;
+        self.read(self.index - 1, |deserialize| seed.deserialize(deserialize))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len - self.index)
+    }
+}
+
+/// An enum whose tag has been read, the variant's fields follow
+struct Variant<'d, 'de> {
+    deserialize: &'d mut Deserialize<'de>,
+    tag: u8,
+}
+
+impl<'d, 'de> de::EnumAccess<'de> for Variant<'d, 'de> {
+    type Error = Error;
+    type Variant = &'d mut Deserialize<'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Error> {
+        let tag: de::value::U32Deserializer<Error> = u32::from(self.tag).into_deserializer();
+        Ok((seed.deserialize(tag)?, self.deserialize))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for &mut Deserialize<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(Items::new(self, len, None))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.nested(|deserialize| {
+            visitor.visit_seq(Items::new(deserialize, fields.len(), Some(fields)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BinaryBuilder, Serializable};
+
+    #[derive(
+        Debug,
+        Clone,
+        Copy,
+        PartialEq,
+        ::serde::Serialize,
+        ::serde::Deserialize,
+        crate::Serializable,
+        crate::Deserializable,
+    )]
+    #[repr(u8)]
+    enum Env {
+        Server = 0,
+        Client = 1,
+    }
+
+    #[derive(
+        Debug,
+        PartialEq,
+        ::serde::Serialize,
+        ::serde::Deserialize,
+        crate::Serializable,
+        crate::Deserializable,
+    )]
+    enum Source {
+        Local,
+        Modrinth {
+            project: String,
+            version: Option<String>,
+        },
+        Url(String, u32),
+    }
+
+    #[derive(
+        Debug,
+        PartialEq,
+        ::serde::Serialize,
+        ::serde::Deserialize,
+        crate::Serializable,
+        crate::Deserializable,
+    )]
+    struct Mod {
+        #[serde(rename = "type")]
+        env: Env,
+        sources: Vec<Source>,
+        hash: Vec<u8>,
+        weight: f64,
+    }
+
+    fn test_mod() -> Mod {
+        Mod {
+            env: Env::Client,
+            sources: vec![
+                Source::Local,
+                Source::Modrinth {
+                    project: String::from("sodium"),
+                    version: None,
+                },
+                Source::Url(String::from("https://example.com"), 443),
+            ],
+            hash: vec![0xde, 0xad, 0, 0xef],
+            weight: -1.5,
+        }
+    }
+
+    #[test]
+    fn serde_matches_derives() {
+        let value = test_mod();
+        let mut buf = Vec::new();
+        to_writer(&mut buf, &value).unwrap();
+        assert_eq!(&*buf, &*Serializable::serialize(&value).unwrap());
+
+        let buf = BinaryBuilder::new(b"mod", 1)
+            .add(&Serde(&value))
+            .unwrap()
+            .build();
+        let mut deserialize = Deserialize::new(&buf, b"mod", 1).unwrap();
+        assert_eq!(deserialize.read_serde::<Mod>().unwrap(), value);
+        deserialize.finish().unwrap();
+    }
+
+    #[test]
+    fn serde_errors_have_offset_and_path() {
+        let mut buf = BinaryBuilder::new(b"mod", 1)
+            .add(&Serde(test_mod()))
+            .unwrap()
+            .build()
+            .to_vec();
+        // The header, version, format and flags, the env and the count of sources, then the
+        // tags of the first two sources
+        let tag = "mod".len() + 4 + 2 + 1;
+        buf[tag] = 7;
+        let mut deserialize = Deserialize::new(&buf, b"mod", 1).unwrap();
+        let err = deserialize.read_serde::<Mod>().unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::InvalidDiscriminant {
+                ty: "Source",
+                value: 7
+            }
+        ));
+        assert_eq!(err.path(), "sources[1]");
+        assert_eq!(err.offset(), tag as u64);
+
+        #[derive(::serde::Serialize)]
+        struct Skipped {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            note: Option<String>,
+        }
+        let err = to_writer(Vec::new(), &Skipped { note: None }).unwrap_err();
+        assert_eq!(err.to_string(), "field `note` cannot be skipped at byte 0");
+    }
+}
@@ -0,0 +1,158 @@
+//! The encoding of `#[oxfmt(tagged)]` structs: the number of fields, then each field as its
+//! tag, its length and its bytes. Readers skip tags they don't know and leave out missing
+//! fields, so fields can be added and removed without breaking older or newer readers.
+
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::{
+    Deserialize, Error, ErrorKind,
+    format::{varint, varint_len},
+};
+
+/// Writes the number of fields that follow
+pub fn write_count<W: Write + ?Sized>(writer: &mut W, count: usize) -> Result<()> {
+    writer.write_all(&varint(count as u64))?;
+    Ok(())
+}
+
+/// Writes a field as its tag and length, followed by the bytes written by `write`
+pub fn write_field<W, F>(writer: &mut W, tag: u32, write: F) -> Result<()>
+where
+    W: Write + ?Sized,
+    F: FnOnce(&mut Vec<u8>) -> Result<()>,
+{
+    let mut buf = Vec::new();
+    write(&mut buf)?;
+    writer.write_all(&varint(tag.into()))?;
+    writer.write_all(&varint(buf.len() as u64))?;
+    writer.write_all(&buf)?;
+    Ok(())
+}
+
+/// The size of a field written by `write_field`, given the size of its bytes
+pub fn field_size_hint(tag: u32, len: usize) -> usize {
+    varint_len(tag.into()) + varint_len(len as u64) + len
+}
+
+/// Reads the fields of a tagged struct, calling `read` with each tag. `read` returns false
+/// for tags it doesn't know, which are skipped, and must read exactly the field's length
+/// otherwise.
+pub fn read_fields<'a, F>(deserialize: &mut Deserialize<'a>, mut read: F) -> Result<(), Error>
+where
+    F: FnMut(&mut Deserialize<'a>, u64) -> Result<bool, Error>,
+{
+    for _ in 0..deserialize.read_len()? {
+        let tag = deserialize.read_varint()?;
+        let len = deserialize.read_usize()?;
+        let start = deserialize.offset();
+        if !read(deserialize, tag)? {
+            deserialize.skip(len)?;
+            continue;
+        }
+        let found = deserialize.offset() - start;
+        if found != len as u64 {
+            let expected = len as u64;
+            return Err(Error::new(
+                ErrorKind::LengthMismatch { expected, found },
+                start,
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BinaryBuilder, Schema, Serializable, Value, Versioned};
+
+    #[derive(Debug, PartialEq, crate::Serializable, crate::Deserializable, crate::Describe)]
+    #[oxfmt(header = "config", version = 1, tagged)]
+    struct Old {
+        #[oxfmt(tag = 1)]
+        id: String,
+        #[oxfmt(tag = 2)]
+        mods: Vec<String>,
+    }
+
+    /// Adds fields in the middle and at the end, and a runtime-only field
+    #[derive(Debug, PartialEq, crate::Serializable, crate::Deserializable, crate::Describe)]
+    #[oxfmt(header = "config", version = 1, tagged)]
+    struct New {
+        #[oxfmt(tag = 1)]
+        id: String,
+        #[oxfmt(tag = 3, default = 2)]
+        loader: u8,
+        #[oxfmt(tag = 2)]
+        mods: Vec<String>,
+        #[oxfmt(tag = 4)]
+        note: Option<String>,
+        #[oxfmt(skip)]
+        dirty: bool,
+    }
+
+    #[test]
+    fn old_and_new_layouts_read_each_other() {
+        let old = Old {
+            id: String::from("pack"),
+            mods: vec![String::from("sodium")],
+        };
+        let new = New {
+            id: String::from("pack"),
+            loader: 0,
+            mods: vec![String::from("sodium")],
+            note: Some(String::from("hi")),
+            dirty: true,
+        };
+
+        let buf = old.serialize().unwrap();
+        let read = New::from_bytes(&buf).unwrap();
+        assert_eq!(read.loader, 2);
+        assert_eq!(read.note, None);
+        assert_eq!(read.mods, old.mods);
+        assert_eq!(New::from_reader(&*buf).unwrap(), read);
+
+        let buf = new.serialize().unwrap();
+        assert_eq!(new.size_hint(), buf.len());
+        assert_eq!(Old::from_bytes(&buf).unwrap(), old);
+        assert_eq!(Old::from_reader(&*buf).unwrap(), old);
+        let read = New::from_bytes(&buf).unwrap();
+        assert_eq!(
+            read,
+            New {
+                dirty: false,
+                ..new
+            }
+        );
+
+        // Schemas skip the tags they don't know too
+        let Value::Struct(fields) = Schema::of::<Old>().read(&buf).unwrap() else {
+            unreachable!()
+        };
+        let names: Vec<_> = fields.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["id", "mods"]);
+    }
+
+    #[test]
+    fn fields_must_fill_their_length() {
+        let mut buf = BinaryBuilder::new(b"config", 1).build().into_vec();
+        write_count(&mut buf, 1).unwrap();
+        // A 3 byte string and a stray byte, which the field's reader leaves behind
+        write_field(&mut buf, 1, |writer| {
+            "ab".serialize_into(writer)?;
+            writer.push(0);
+            Ok(())
+        })
+        .unwrap();
+        let err = Old::from_bytes(&buf).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::LengthMismatch {
+                expected: 4,
+                found: 3
+            }
+        ));
+    }
+}
@@ -1,129 +1,568 @@
-use anyhow::{Result, bail};
-use downcast_rs::{Downcast, impl_downcast};
+use anyhow::Result;
 use std::{
-    alloc::{Layout, alloc},
-    any::Any,
-    collections::HashMap,
-    mem::MaybeUninit,
-    ptr::copy_nonoverlapping,
-    slice,
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    hash::{BuildHasher, Hash},
+    io::{Read, Write},
 };
 
+use crate::{
+    Deserialize, Error, ErrorKind, Limits,
+    format::{varint, varint_len},
+};
+use oxfmt_derive::{Deserializable, Serializable};
+
 pub trait Serializable {
-    fn serialize(&self) -> Result<Box<[u8]>>;
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()>;
+
+    /// About how many bytes `serialize_into` writes, used to size buffers up front. It doesn't
+    /// need to be exact, 0 means unknown.
+    fn size_hint(&self) -> usize {
+        0
+    }
+
+    fn serialize(&self) -> Result<Box<[u8]>> {
+        let mut buf = Vec::with_capacity(self.size_hint());
+        self.serialize_into(&mut buf)?;
+        Ok(buf.into_boxed_slice())
+    }
+}
+
+// Strings are written as their length followed by the UTF-8 bytes
+impl Serializable for str {
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&varint(self.len() as u64))?;
+        writer.write_all(self.as_bytes())?;
+        Ok(())
+    }
+
+    fn size_hint(&self) -> usize {
+        varint_len(self.len() as u64) + self.len()
+    }
 }
 
 impl Serializable for String {
-    fn serialize(&self) -> Result<Box<[u8]>> {
-        let len = self.len() + 1;
-        let layout = Layout::array::<u8>(len)?;
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        self.as_str().serialize_into(writer)
+    }
 
-        let ptr = unsafe { alloc(layout) };
-        if ptr.is_null() {
-            bail!("allocation failed");
-        }
+    fn size_hint(&self) -> usize {
+        self.as_str().size_hint()
+    }
+}
 
-        unsafe {
-            copy_nonoverlapping(self.as_ptr(), ptr, self.len());
-            *ptr.add(self.len()) = 0;
+// Fixed width numbers are written little endian
+macro_rules! impl_le_bytes {
+    ($($ty:ty => $read:ident),* $(,)?) => {$(
+        impl Serializable for $ty {
+            fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+                Ok(writer.write_all(&self.to_le_bytes())?)
+            }
 
-            let slice = slice::from_raw_parts_mut(ptr, len);
-            Ok(Box::from_raw(slice))
+            fn size_hint(&self) -> usize {
+                size_of::<$ty>()
+            }
         }
-    }
+
+        impl<'a> Deserializable<'a> for $ty {
+            fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+                deserialize.$read()
+            }
+        }
+    )*};
 }
 
-impl Serializable for u8 {
-    fn serialize(&self) -> Result<Box<[u8]>> {
-        Ok(Box::new(self.to_le_bytes()))
+impl_le_bytes! {
+    u8 => read_u8,
+    u16 => read_u16,
+    u32 => read_u32,
+    u64 => read_u64,
+    u128 => read_u128,
+    i8 => read_i8,
+    i16 => read_i16,
+    i32 => read_i32,
+    i64 => read_i64,
+    i128 => read_i128,
+    f32 => read_f32,
+    f64 => read_f64,
+}
+
+// Booleans are a single byte, 0 for `false` and 1 for `true`
+impl Serializable for bool {
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        Ok(writer.write_all(&[*self as u8])?)
+    }
+
+    fn size_hint(&self) -> usize {
+        1
     }
 }
 
-impl Serializable for u16 {
-    fn serialize(&self) -> Result<Box<[u8]>> {
-        Ok(Box::new(self.to_le_bytes()))
+// Sizes are written as varints so they don't depend on the writer's pointer width
+impl Serializable for usize {
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        Ok(writer.write_all(&varint(*self as u64))?)
+    }
+
+    fn size_hint(&self) -> usize {
+        varint_len(*self as u64)
     }
 }
 
-impl Serializable for u32 {
-    fn serialize(&self) -> Result<Box<[u8]>> {
-        Ok(Box::new(self.to_le_bytes()))
+// Blobs share the encoding of `Vec<u8>`: the length followed by the raw bytes
+impl Serializable for [u8] {
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&varint(self.len() as u64))?;
+        writer.write_all(self)?;
+        Ok(())
+    }
+
+    fn size_hint(&self) -> usize {
+        varint_len(self.len() as u64) + self.len()
     }
 }
 
-impl Serializable for u64 {
-    fn serialize(&self) -> Result<Box<[u8]>> {
-        Ok(Box::new(self.to_le_bytes()))
+impl<T: Serializable + ?Sized> Serializable for Box<T> {
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        (**self).serialize_into(writer)
+    }
+
+    fn size_hint(&self) -> usize {
+        (**self).size_hint()
     }
 }
 
-impl Serializable for u128 {
-    fn serialize(&self) -> Result<Box<[u8]>> {
-        Ok(Box::new(self.to_le_bytes()))
+impl<T: Serializable + ?Sized> Serializable for &T {
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        (**self).serialize_into(writer)
+    }
+
+    fn size_hint(&self) -> usize {
+        (**self).size_hint()
     }
 }
 
-impl Serializable for usize {
-    fn serialize(&self) -> Result<Box<[u8]>> {
-        Ok(Box::new(self.to_le_bytes()))
+impl<T: Serializable + ToOwned + ?Sized> Serializable for Cow<'_, T> {
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        (**self).serialize_into(writer)
+    }
+
+    fn size_hint(&self) -> usize {
+        (**self).size_hint()
     }
 }
 
-impl Serializable for Box<[u8]> {
-    fn serialize(&self) -> Result<Box<[u8]>> {
-        Ok(Box::clone(self))
+// Tuples are written as their items in order
+macro_rules! impl_tuple {
+    ($(($($item:ident),+)),* $(,)?) => {$(
+        impl<$($item: Serializable),+> Serializable for ($($item,)+) {
+            #[allow(non_snake_case)]
+            fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+                let ($($item,)+) = self;
+                $( $item.serialize_into(writer)?; )+
+                Ok(())
+            }
+
+            #[allow(non_snake_case)]
+            fn size_hint(&self) -> usize {
+                let ($($item,)+) = self;
+                0 $( + $item.size_hint() )+
+            }
+        }
+
+        impl<'a, $($item: Deserializable<'a>),+> Deserializable<'a> for ($($item,)+) {
+            fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+                Ok(($($item::deserialize(deserialize)?,)+))
+            }
+        }
+    )*};
+}
+
+impl_tuple! {
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+}
+
+// Arrays have a fixed length, so only their items are written
+impl<T: Serializable, const N: usize> Serializable for [T; N] {
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        for item in self {
+            item.serialize_into(writer)?;
+        }
+        Ok(())
+    }
+
+    fn size_hint(&self) -> usize {
+        self.iter().map(Serializable::size_hint).sum()
     }
 }
 
 impl<T: Serializable> Serializable for Vec<T> {
-    fn serialize(&self) -> Result<Box<[u8]>> {
-        let serialized: Vec<Box<[u8]>> = self
-            .iter()
-            .map(|item| item.serialize())
-            .collect::<Result<_>>()?;
-        let count = self.len();
-        let payload_len: usize = serialized.iter().map(|b| b.len()).sum();
-        let total_len = size_of::<usize>() + payload_len;
-
-        let mut boxed_uninit: Box<[MaybeUninit<u8>]> = Box::new_uninit_slice(total_len);
-        let ptr = boxed_uninit.as_mut_ptr() as *mut u8;
-
-        unsafe {
-            copy_nonoverlapping(count.to_le_bytes().as_ptr(), ptr, size_of::<usize>());
-            let mut offset = size_of::<usize>();
-            for b in serialized {
-                copy_nonoverlapping(b.as_ptr(), ptr.add(offset), b.len());
-                offset += b.len();
-            }
-            Ok(boxed_uninit.assume_init())
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&varint(self.len() as u64))?;
+        for item in self {
+            item.serialize_into(writer)?;
         }
+        Ok(())
+    }
+
+    fn size_hint(&self) -> usize {
+        items_size_hint(self.len(), self)
     }
 }
 
+// Options are prefixed with a presence byte, 0 for `None` and 1 for `Some`
 impl<T: Serializable> Serializable for Option<T> {
-    fn serialize(&self) -> Result<Box<[u8]>> {
-        Ok(match self {
-            Some(val) => val.serialize()?,
-            None => Box::from([]),
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        match self {
+            Some(val) => {
+                writer.write_all(&[1])?;
+                val.serialize_into(writer)
+            }
+            None => Ok(writer.write_all(&[0])?),
+        }
+    }
+
+    fn size_hint(&self) -> usize {
+        1 + self.as_ref().map_or(0, Serializable::size_hint)
+    }
+}
+
+// Maps are written like a `Vec` of key value pairs and sets like a `Vec` of their items, hash
+// based ones are sorted first so the output doesn't depend on the iteration order
+impl<K: Serializable + Ord, V: Serializable, S> Serializable for HashMap<K, V, S> {
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_unstable_by_key(|&(key, _)| key);
+        write_entries(writer, self.len(), entries)
+    }
+
+    fn size_hint(&self) -> usize {
+        entries_size_hint(self.len(), self)
+    }
+}
+
+impl<K: Serializable, V: Serializable> Serializable for BTreeMap<K, V> {
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        write_entries(writer, self.len(), self)
+    }
+
+    fn size_hint(&self) -> usize {
+        entries_size_hint(self.len(), self)
+    }
+}
+
+impl<T: Serializable + Ord, S> Serializable for HashSet<T, S> {
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        let mut items: Vec<_> = self.iter().collect();
+        items.sort_unstable();
+        write_items(writer, self.len(), items)
+    }
+
+    fn size_hint(&self) -> usize {
+        items_size_hint(self.len(), self)
+    }
+}
+
+impl<T: Serializable> Serializable for BTreeSet<T> {
+    fn serialize_into<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        write_items(writer, self.len(), self)
+    }
+
+    fn size_hint(&self) -> usize {
+        items_size_hint(self.len(), self)
+    }
+}
+
+fn write_entries<'a, K, V, W>(
+    writer: &mut W,
+    len: usize,
+    entries: impl IntoIterator<Item = (&'a K, &'a V)>,
+) -> Result<()>
+where
+    K: Serializable + 'a,
+    V: Serializable + 'a,
+    W: Write + ?Sized,
+{
+    writer.write_all(&varint(len as u64))?;
+    for (key, value) in entries {
+        key.serialize_into(writer)?;
+        value.serialize_into(writer)?;
+    }
+    Ok(())
+}
+
+fn write_items<'a, T, W>(
+    writer: &mut W,
+    len: usize,
+    items: impl IntoIterator<Item = &'a T>,
+) -> Result<()>
+where
+    T: Serializable + 'a,
+    W: Write + ?Sized,
+{
+    writer.write_all(&varint(len as u64))?;
+    for item in items {
+        item.serialize_into(writer)?;
+    }
+    Ok(())
+}
+
+fn entries_size_hint<'a, K, V>(
+    len: usize,
+    entries: impl IntoIterator<Item = (&'a K, &'a V)>,
+) -> usize
+where
+    K: Serializable + 'a,
+    V: Serializable + 'a,
+{
+    let entries: usize = entries
+        .into_iter()
+        .map(|(key, value)| key.size_hint() + value.size_hint())
+        .sum();
+    varint_len(len as u64) + entries
+}
+
+fn items_size_hint<'a, T: Serializable + 'a>(
+    len: usize,
+    items: impl IntoIterator<Item = &'a T>,
+) -> usize {
+    let items: usize = items.into_iter().map(Serializable::size_hint).sum();
+    varint_len(len as u64) + items
+}
+
+/// Reads a value from a [`Deserialize`], `'a` is the lifetime of the buffer being read, which
+/// lets types like `&'a str` borrow from it instead of copying
+pub trait Deserializable<'a>: Sized {
+    fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error>;
+}
+
+impl<'a> Deserializable<'a> for String {
+    fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+        deserialize.read_string()
+    }
+}
+
+impl<'a> Deserializable<'a> for bool {
+    fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+        deserialize.read_bool()
+    }
+}
+
+impl<'a> Deserializable<'a> for usize {
+    fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+        deserialize.read_usize()
+    }
+}
+
+impl<'a, T: Deserializable<'a>, const N: usize> Deserializable<'a> for [T; N] {
+    fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+        let items = (0..N)
+            .map(|i| T::deserialize(deserialize).map_err(|err| err.at_index(i)))
+            .collect::<Result<Vec<_>, _>>()?;
+        match items.try_into() {
+            Ok(array) => Ok(array),
+            Err(_) => unreachable!("exactly N items were read"),
+        }
+    }
+}
+
+impl<'a> Deserializable<'a> for Box<[u8]> {
+    fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+        Ok(deserialize
+            .read_cow_bytes()?
+            .into_owned()
+            .into_boxed_slice())
+    }
+}
+
+impl<'a, T: Deserializable<'a>> Deserializable<'a> for Box<T> {
+    fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+        T::deserialize(deserialize).map(Box::new)
+    }
+}
+
+// Borrowed types can only be read from buffers, `Cow` borrows from buffers and copies from streams
+impl<'de: 'a, 'a> Deserializable<'de> for &'a str {
+    fn deserialize(deserialize: &mut Deserialize<'de>) -> Result<Self, Error> {
+        deserialize.read_str()
+    }
+}
+
+impl<'de: 'a, 'a> Deserializable<'de> for &'a [u8] {
+    fn deserialize(deserialize: &mut Deserialize<'de>) -> Result<Self, Error> {
+        deserialize.read_blob()
+    }
+}
+
+impl<'de: 'a, 'a> Deserializable<'de> for Cow<'a, str> {
+    fn deserialize(deserialize: &mut Deserialize<'de>) -> Result<Self, Error> {
+        deserialize.read_cow_str()
+    }
+}
+
+impl<'de: 'a, 'a> Deserializable<'de> for Cow<'a, [u8]> {
+    fn deserialize(deserialize: &mut Deserialize<'de>) -> Result<Self, Error> {
+        deserialize.read_cow_bytes()
+    }
+}
+
+impl<'a, T: Deserializable<'a>> Deserializable<'a> for Vec<T> {
+    fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+        deserialize.read_vec()
+    }
+}
+
+impl<'a, T: Deserializable<'a>> Deserializable<'a> for Option<T> {
+    fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+        deserialize.read_option()
+    }
+}
+
+fn read_entry<'a, K, V>(deserialize: &mut Deserialize<'a>) -> Result<(K, V), Error>
+where
+    K: Deserializable<'a>,
+    V: Deserializable<'a>,
+{
+    Ok((K::deserialize(deserialize)?, V::deserialize(deserialize)?))
+}
+
+// Duplicate keys can't come from a serialized map, so they're treated as corruption
+impl<'a, K, V, S> Deserializable<'a> for HashMap<K, V, S>
+where
+    K: Deserializable<'a> + Eq + Hash,
+    V: Deserializable<'a>,
+    S: BuildHasher + Default,
+{
+    fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+        deserialize.nested(|deserialize| {
+            let mut map = HashMap::default();
+            for i in 0..deserialize.read_len()? {
+                let offset = deserialize.offset();
+                let (key, value) = read_entry(deserialize).map_err(|err| err.at_index(i))?;
+                if map.insert(key, value).is_some() {
+                    return Err(Error::new(ErrorKind::DuplicateKey, offset).at_index(i));
+                }
+            }
+            Ok(map)
+        })
+    }
+}
+
+impl<'a, K, V> Deserializable<'a> for BTreeMap<K, V>
+where
+    K: Deserializable<'a> + Ord,
+    V: Deserializable<'a>,
+{
+    fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+        deserialize.nested(|deserialize| {
+            let mut map = BTreeMap::new();
+            for i in 0..deserialize.read_len()? {
+                let offset = deserialize.offset();
+                let (key, value) = read_entry(deserialize).map_err(|err| err.at_index(i))?;
+                if map.insert(key, value).is_some() {
+                    return Err(Error::new(ErrorKind::DuplicateKey, offset).at_index(i));
+                }
+            }
+            Ok(map)
+        })
+    }
+}
+
+impl<'a, T, S> Deserializable<'a> for HashSet<T, S>
+where
+    T: Deserializable<'a> + Eq + Hash,
+    S: BuildHasher + Default,
+{
+    fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+        deserialize.nested(|deserialize| {
+            let mut set = HashSet::default();
+            for i in 0..deserialize.read_len()? {
+                let offset = deserialize.offset();
+                let item = T::deserialize(deserialize).map_err(|err| err.at_index(i))?;
+                if !set.insert(item) {
+                    return Err(Error::new(ErrorKind::DuplicateKey, offset).at_index(i));
+                }
+            }
+            Ok(set)
+        })
+    }
+}
+
+impl<'a, T: Deserializable<'a> + Ord> Deserializable<'a> for BTreeSet<T> {
+    fn deserialize(deserialize: &mut Deserialize<'a>) -> Result<Self, Error> {
+        deserialize.nested(|deserialize| {
+            let mut set = BTreeSet::new();
+            for i in 0..deserialize.read_len()? {
+                let offset = deserialize.offset();
+                let item = T::deserialize(deserialize).map_err(|err| err.at_index(i))?;
+                if !set.insert(item) {
+                    return Err(Error::new(ErrorKind::DuplicateKey, offset).at_index(i));
+                }
+            }
+            Ok(set)
         })
     }
 }
 
-// Cursed code for runtime reflection
-pub trait Deserializable: Downcast {
-    fn get_structure() -> Structure
-    where
-        Self: Sized;
-    fn construct(fields: Vec<Box<dyn Any>>) -> Result<Self>
-    where
-        Self: Sized;
+/// A top level type written with a header and version, which can also read its older versions.
+///
+/// Deriving `Deserializable` on a type with `#[oxfmt(header = ..., version = ...)]` implements
+/// this. Older versions are registered with `#[oxfmt(upgrade(1 = V1, 2 = V2))]`, where each
+/// type reads that version's layout and converts into the next one with `From`.
+pub trait Versioned<'a>: Deserializable<'a> {
+    const HEADER: &'static [u8];
+    const VERSION: u16;
+    const MIN_VERSION: u16;
+
+    /// Reads a value written with an older `version`
+    fn upgrade(version: u16, deserialize: &mut Deserialize<'a>) -> Result<Self, Error>;
+
+    fn from_bytes(buf: &'a [u8]) -> Result<Self, Error> {
+        Self::from_bytes_with_limits(buf, Limits::default())
+    }
+
+    /// Reads from a stream, verifying the checksum trailer once the value has been read
+    fn from_reader<R: Read + 'a>(reader: R) -> Result<Self, Error> {
+        Self::from_reader_with_limits(reader, Limits::default())
+    }
+
+    /// Like [`from_bytes`](Versioned::from_bytes), for untrusted data
+    fn from_bytes_with_limits(buf: &'a [u8], limits: Limits) -> Result<Self, Error> {
+        let versions = Self::MIN_VERSION..=Self::VERSION;
+        let deserialize = Deserialize::new_with_limits(buf, Self::HEADER, versions, limits)?;
+        read_versioned(deserialize)
+    }
+
+    /// Like [`from_reader`](Versioned::from_reader), for untrusted data
+    fn from_reader_with_limits<R: Read + 'a>(reader: R, limits: Limits) -> Result<Self, Error> {
+        let versions = Self::MIN_VERSION..=Self::VERSION;
+        let deserialize = Deserialize::from_reader(reader, Self::HEADER, versions)?;
+        read_versioned(deserialize.with_limits(limits))
+    }
+}
+
+fn read_versioned<'a, T: Versioned<'a>>(mut deserialize: Deserialize<'a>) -> Result<T, Error> {
+    let value = match deserialize.version() {
+        version if version == T::VERSION => T::deserialize(&mut deserialize)?,
+        version => T::upgrade(version, &mut deserialize)?,
+    };
+    deserialize.finish()?;
+    Ok(value)
 }
-impl_downcast!(Deserializable);
 
+/// Describes the layout of a serialized struct or enum variant, field by field
+#[derive(Debug, Clone, PartialEq, Serializable, Deserializable)]
 pub struct Structure {
-    pub fields: HashMap<usize, Field>,
+    pub name: String,
+    /// Fields in the order they're written, tuple fields are named by their index
+    pub fields: Vec<(String, Field)>,
 }
 
+/// How a value is laid out, see [`Describe`](crate::Describe). Schemas are saved with this
+/// encoding, so new variants go at the end
+#[derive(Debug, Clone, PartialEq, Serializable, Deserializable)]
 pub enum Field {
     String,
     U8,
@@ -131,6 +570,56 @@ pub enum Field {
     U32,
     U64,
     U128,
-    Struct,
-    Vector,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    F32,
+    F64,
+    Bool,
+    Bytes,
+    Struct(Structure),
+    Vector(Box<Field>),
+    Option(Box<Field>),
+    Map(Box<Field>, Box<Field>),
+    Set(Box<Field>),
+    /// Enums with data, one structure per variant in tag order
+    Enum {
+        name: String,
+        variants: Vec<Structure>,
+    },
+    /// Fieldless `#[repr(u8)]` enums, the name and discriminant of each variant
+    Repr {
+        name: String,
+        variants: Vec<(String, u8)>,
+    },
+    /// A varint, as written for `usize`
+    Usize,
+    Tuple(Vec<Field>),
+    /// `#[oxfmt(tagged)]` structs, the tag, name and layout of each field
+    Tagged {
+        name: String,
+        fields: Vec<(u32, String, Field)>,
+    },
+    /// A type inside its own layout, read like the innermost enclosing struct or enum with this
+    /// name
+    Recursive(String),
+}
+impl Field {
+    pub fn vector(field: Field) -> Self {
+        Field::Vector(Box::new(field))
+    }
+
+    pub fn option(field: Field) -> Self {
+        Field::Option(Box::new(field))
+    }
+
+    pub fn map(key: Field, value: Field) -> Self {
+        Field::Map(Box::new(key), Box::new(value))
+    }
+
+    pub fn set(field: Field) -> Self {
+        Field::Set(Box::new(field))
+    }
 }
@@ -0,0 +1,230 @@
+//! The encodings generated by the derives: enums with data, field attributes, tuple and
+//! generic structs, and maps.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use oxfmt::{
+    BinaryBuilder, Describe, Deserializable, Deserialize, ErrorKind, Schema, Serializable, Value,
+    Versioned,
+};
+
+#[test]
+fn maps_are_deterministic() {
+    let first: HashMap<String, u32> = [("b".into(), 2), ("a".into(), 1), ("c".into(), 3)].into();
+    let mut second = HashMap::new();
+    for (key, value) in [("c", 3), ("a", 1), ("b", 2)] {
+        second.insert(key.to_string(), value);
+    }
+    let buf = first.serialize().unwrap();
+    assert_eq!(buf, second.serialize().unwrap());
+    let sorted: BTreeMap<String, u32> = first.clone().into_iter().collect();
+    assert_eq!(buf, sorted.serialize().unwrap());
+
+    let buf = BinaryBuilder::new(b"maps", 1).add(&first).unwrap().build();
+    let mut deserialize = Deserialize::new(&buf, b"maps", 1).unwrap();
+    let read: HashMap<String, u32> = deserialize.read_struct().unwrap();
+    assert_eq!(read, first);
+
+    let duplicate = BinaryBuilder::new(b"sets", 1)
+        .add(&vec![1u8, 1])
+        .unwrap()
+        .build();
+    let mut deserialize = Deserialize::new(&duplicate, b"sets", 1).unwrap();
+    assert!(deserialize.read_struct::<BTreeSet<u8>>().is_err());
+}
+
+#[derive(Debug, PartialEq, Serializable, Deserializable)]
+enum Source {
+    Local,
+    Modrinth {
+        project: String,
+        version: Option<String>,
+    },
+    Url(String, u32),
+}
+
+#[test]
+fn enums_with_data_are_tagged() {
+    let sources = vec![
+        Source::Url("https://example.com/mod.jar".into(), 7),
+        Source::Local,
+        Source::Modrinth {
+            project: "sodium".into(),
+            version: None,
+        },
+    ];
+    let buf = sources.serialize().unwrap();
+    assert_eq!(buf[..2], [3, 2]);
+
+    let buf = BinaryBuilder::new(b"enums", 1)
+        .add(&sources)
+        .unwrap()
+        .build();
+    let mut deserialize = Deserialize::new(&buf, b"enums", 1).unwrap();
+    assert_eq!(deserialize.read_struct::<Vec<Source>>().unwrap(), sources);
+
+    // The last tag is followed by the length of "sodium", its bytes and the `None` tag
+    let mut corrupt = buf.to_vec();
+    corrupt[buf.len() - 9] = 3;
+    let mut deserialize = Deserialize::new(&corrupt, b"enums", 1).unwrap();
+    let err = deserialize.read_struct::<Vec<Source>>().unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::InvalidDiscriminant {
+            ty: "Source",
+            value: 3
+        }
+    ));
+    assert_eq!(err.path(), "[2]");
+}
+
+/// Durations written as whole seconds
+mod seconds {
+    use std::{io::Write, time::Duration};
+
+    use oxfmt::{Deserializable, Deserialize, Error, Field, Serializable};
+
+    pub fn serialize_into<W: Write + ?Sized>(
+        value: &Duration,
+        writer: &mut W,
+    ) -> anyhow::Result<()> {
+        value.as_secs().serialize_into(writer)
+    }
+
+    pub fn deserialize(deserialize: &mut Deserialize) -> Result<Duration, Error> {
+        u64::deserialize(deserialize).map(Duration::from_secs)
+    }
+
+    pub fn describe() -> Field {
+        Field::U64
+    }
+}
+
+#[derive(Debug, PartialEq, Serializable, Deserializable, Describe)]
+#[oxfmt(header = "task", version = 1)]
+struct Task {
+    #[oxfmt(rename = "title")]
+    name: String,
+    #[oxfmt(skip)]
+    runs: u32,
+    #[oxfmt(with = seconds)]
+    timeout: std::time::Duration,
+    #[oxfmt(skip, default = String::from("idle"))]
+    status: String,
+    step: Step,
+}
+
+#[derive(Debug, PartialEq, Serializable, Deserializable, Describe)]
+enum Step {
+    Run {
+        #[oxfmt(with = seconds)]
+        delay: std::time::Duration,
+        #[oxfmt(skip)]
+        attempts: u8,
+    },
+}
+
+#[test]
+fn field_attributes() {
+    let task = Task {
+        name: "build".into(),
+        runs: 3,
+        timeout: std::time::Duration::from_millis(2500),
+        status: "running".into(),
+        step: Step::Run {
+            delay: std::time::Duration::from_secs(1),
+            attempts: 2,
+        },
+    };
+    let buf = task.serialize().unwrap();
+    let expected = BinaryBuilder::new(b"task", 1)
+        .add("build")
+        .unwrap()
+        .add(&2u64)
+        .unwrap()
+        .add(&0u8)
+        .unwrap()
+        .add(&1u64)
+        .unwrap()
+        .build();
+    assert_eq!(buf, expected);
+
+    let read = Task::from_bytes(&buf).unwrap();
+    assert_eq!(read.runs, 0);
+    assert_eq!(read.timeout, std::time::Duration::from_secs(2));
+    assert_eq!(read.status, "idle");
+    assert_eq!(
+        read.step,
+        Step::Run {
+            delay: std::time::Duration::from_secs(1),
+            attempts: 0
+        }
+    );
+
+    let err = Task::from_bytes(&buf[..buf.len() - 1]).unwrap_err();
+    assert_eq!(err.path(), "step.delay");
+    let Value::Struct(fields) = Schema::of::<Task>().read(&buf).unwrap() else {
+        unreachable!()
+    };
+    let names: Vec<_> = fields.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["title", "timeout", "step"]);
+    assert_eq!(fields[1].1, Value::Unsigned(2));
+}
+
+#[derive(Debug, PartialEq, Serializable, Deserializable, Describe)]
+struct Digest([u8; 4]);
+
+#[derive(Debug, PartialEq, Serializable, Deserializable, Describe)]
+struct ModId(String);
+
+#[derive(Debug, PartialEq, Serializable, Deserializable, Describe)]
+struct Marker;
+
+/// `C` is only used by a skipped field, so it doesn't need to be serializable
+#[derive(Debug, PartialEq, Serializable, Deserializable, Describe)]
+#[oxfmt(header = "listing", version = 1)]
+struct Listing<K, V, C> {
+    items: Vec<(K, V)>,
+    hash: Digest,
+    marker: Marker,
+    #[oxfmt(skip)]
+    cache: Vec<C>,
+}
+
+#[test]
+fn tuple_and_generic_structs() {
+    let listing: Listing<ModId, u32, std::time::Instant> = Listing {
+        items: vec![(ModId("sodium".into()), 7)],
+        hash: Digest([1, 2, 3, 4]),
+        marker: Marker,
+        cache: Vec::new(),
+    };
+    let buf = listing.serialize().unwrap();
+    let expected = BinaryBuilder::new(b"listing", 1)
+        .add(&1usize)
+        .unwrap()
+        .add("sodium")
+        .unwrap()
+        .add(&7u32)
+        .unwrap()
+        .add(&[1u8, 2, 3, 4])
+        .unwrap()
+        .build();
+    assert_eq!(buf, expected);
+    assert_eq!(Listing::from_bytes(&buf).unwrap(), listing);
+
+    let err = Listing::<ModId, u32, ()>::from_bytes(&buf[..buf.len() - 1]).unwrap_err();
+    assert_eq!(err.path(), "hash.0[3]");
+
+    let schema = Schema::of::<Listing<ModId, u32, ()>>();
+    let Value::Struct(fields) = schema.read(&buf).unwrap() else {
+        unreachable!()
+    };
+    assert_eq!(
+        fields[1].1,
+        Value::Struct(vec![(
+            String::from("0"),
+            Value::Vector((1..=4).map(Value::Unsigned).collect())
+        )])
+    );
+}
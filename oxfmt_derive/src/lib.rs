@@ -1,7 +1,13 @@
 use proc_macro::TokenStream;
-use quote::quote;
+use proc_macro2::{Span, TokenTree};
+use quote::{format_ident, quote, ToTokens};
 use syn::punctuated::Punctuated;
-use syn::{parse_macro_input, Attribute, Data, DataEnum, DataStruct, DeriveInput, Meta, Path};
+use syn::token::Comma;
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Data, DataEnum, DataStruct, DeriveInput, Expr,
+    Fields, GenericArgument, GenericParam, Generics, Ident, Index, Lifetime, LifetimeParam, LitInt,
+    LitStr, Meta, Path, PathArguments, Type, Variant,
+};
 
 fn has_repr_u8(attrs: &[Attribute]) -> bool {
     for attr in attrs {
@@ -22,95 +28,736 @@ fn has_repr_u8(attrs: &[Attribute]) -> bool {
     false
 }
 
-#[proc_macro_derive(Serializable, attributes(oxfmt))]
-pub fn serializable_derive(input: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(input as DeriveInput);
-    let name = &input.ident;
-    let generics = &input.generics;
-    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+/// Adds the `'__de` lifetime of the buffer being read, which has to outlive any lifetime the
+/// type borrows for
+fn de_generics(generics: &Generics) -> Generics {
+    let mut de_lifetime = LifetimeParam::new(Lifetime::new("'__de", Span::call_site()));
+    de_lifetime
+        .bounds
+        .extend(generics.lifetimes().map(|param| param.lifetime.clone()));
+    let mut generics = generics.clone();
+    generics
+        .params
+        .insert(0, GenericParam::Lifetime(de_lifetime));
+    generics
+}
+
+/// Adds `bound` to every type parameter used by a field that is written with its own impl,
+/// fields that are skipped or have a `with` codec don't need it. When reading, fields with
+/// `from` are bounded through the type they're read as.
+fn bounded_generics(
+    input: &DeriveInput,
+    bound: proc_macro2::TokenStream,
+    read: bool,
+) -> syn::Result<Generics> {
+    let fields: Vec<&syn::Field> = match &input.data {
+        Data::Struct(DataStruct { fields, .. }) => fields.iter().collect(),
+        Data::Enum(DataEnum { variants, .. }) => variants.iter().flat_map(|v| &v.fields).collect(),
+        Data::Union(_) => Vec::new(),
+    };
+    let tagged = parse_container_attrs(input)?.tagged;
+    let mut types = Vec::new();
+    for field in fields {
+        let attrs = parse_field_attrs(field, tagged)?;
+        match &attrs.from {
+            _ if attrs.skip || attrs.with.is_some() => {}
+            Some(from_ty) if read => types.push(from_ty.to_token_stream()),
+            _ => types.push(field.ty.to_token_stream()),
+        }
+    }
+    let mut generics = input.generics.clone();
+    let params: Vec<Ident> = generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect();
+    for param in params {
+        if types.iter().any(|ty| mentions(ty.clone(), &param)) {
+            generics
+                .make_where_clause()
+                .predicates
+                .push(parse_quote! { #param: #bound });
+        }
+    }
+    Ok(generics)
+}
+
+fn mentions(tokens: proc_macro2::TokenStream, ident: &Ident) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(token) => token == *ident,
+        TokenTree::Group(group) => mentions(group.stream(), ident),
+        _ => false,
+    })
+}
+
+/// Builds `path` out of field values given in declaration order
+fn construct(
+    path: proc_macro2::TokenStream,
+    fields: &Fields,
+    values: impl Iterator<Item = proc_macro2::TokenStream>,
+) -> proc_macro2::TokenStream {
+    match fields {
+        Fields::Named(fields) => {
+            let idents = fields.named.iter().map(|field| &field.ident);
+            quote! { #path { #( #idents: #values ),* } }
+        }
+        Fields::Unnamed(_) => quote! { #path( #( #values ),* ) },
+        Fields::Unit => quote! { #path },
+    }
+}
+
+/// Fieldless `#[repr(u8)]` enums are written as their discriminant, any other enum is written
+/// as the index of its variant followed by the variant's fields
+fn is_fieldless_repr_u8(attrs: &[Attribute], variants: &Punctuated<Variant, Comma>) -> bool {
+    has_repr_u8(attrs) && variants.iter().all(|v| v.fields.is_empty())
+}
+
+fn check_variant_count(ident: &Ident, variants: &Punctuated<Variant, Comma>) -> syn::Result<()> {
+    if variants.len() > 256 {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "enums with more than 256 variants are not supported",
+        ));
+    }
+    Ok(())
+}
+
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    }
+}
 
-    // Parse #[oxfmt(header = ..., version = ...)]
-    let mut header: Option<syn::Lit> = None;
-    let mut version: Option<syn::Lit> = None;
+/// Type level attributes: `#[oxfmt(header = ..., version = ..., checksum, tagged,
+/// compress = "zstd" | "deflate", upgrade(1 = ..., ...))]`
+#[derive(Default)]
+struct ContainerAttrs {
+    header: Option<syn::Lit>,
+    version: Option<syn::Lit>,
+    checksum: bool,
+    /// The `oxfmt::Compression` variant the data after the header is compressed with, which
+    /// needs the oxfmt feature of the same name
+    compress: Option<Ident>,
+    /// Fields are written with a tag and length, see `oxfmt::tagged`
+    tagged: bool,
+    /// Older versions and the types that read them, sorted by version
+    upgrades: Vec<(u16, Type)>,
+}
+
+fn parse_container_attrs(input: &DeriveInput) -> syn::Result<ContainerAttrs> {
+    let is_struct = matches!(input.data, Data::Struct(_));
+    let mut container = ContainerAttrs::default();
+    let mut upgrades: Vec<(syn::LitInt, Type)> = Vec::new();
     for attr in &input.attrs {
         if attr.path().is_ident("oxfmt") {
-            let _ = attr.parse_args_with(|input: syn::parse::ParseStream| {
+            attr.parse_args_with(|input: syn::parse::ParseStream| {
                 while !input.is_empty() {
-                    let lookahead = input.lookahead1();
-                    if lookahead.peek(syn::Ident) {
-                        let ident: syn::Ident = input.parse()?;
+                    let ident: syn::Ident = input.parse()?;
+                    if ident == "checksum" {
+                        container.checksum = true;
+                    } else if ident == "tagged" {
+                        if !is_struct {
+                            return Err(syn::Error::new_spanned(
+                                &ident,
+                                "only structs can be tagged",
+                            ));
+                        }
+                        container.tagged = true;
+                    } else if ident == "upgrade" {
+                        let content;
+                        syn::parenthesized!(content in input);
+                        while !content.is_empty() {
+                            let version: syn::LitInt = content.parse()?;
+                            let _eq: syn::Token![=] = content.parse()?;
+                            let ty: Type = content.parse()?;
+                            upgrades.push((version, ty));
+                            if content.peek(syn::Token![,]) {
+                                let _: syn::Token![,] = content.parse()?;
+                            }
+                        }
+                    } else {
                         let _eq: syn::Token![=] = input.parse()?;
                         let lit: syn::Lit = input.parse()?;
                         match ident.to_string().as_str() {
-                            "header" => header = Some(lit),
-                            "version" => version = Some(lit),
-                            _ => {}
-                        }
-                        if input.peek(syn::Token![,]) {
-                            let _: syn::Token![,] = input.parse()?;
+                            "header" => container.header = Some(lit),
+                            "version" => container.version = Some(lit),
+                            "compress" => {
+                                let compression = match &lit {
+                                    syn::Lit::Str(name) if name.value() == "zstd" => "Zstd",
+                                    syn::Lit::Str(name) if name.value() == "deflate" => "Deflate",
+                                    _ => {
+                                        return Err(syn::Error::new_spanned(
+                                            &lit,
+                                            "expected \"zstd\" or \"deflate\"",
+                                        ));
+                                    }
+                                };
+                                container.compress = Some(Ident::new(compression, lit.span()));
+                            }
+                            _ => {
+                                return Err(syn::Error::new_spanned(
+                                    &ident,
+                                    "unknown oxfmt attribute, expected `header`, `version`, `checksum`, `tagged`, `compress` or `upgrade`",
+                                ));
+                            }
                         }
-                    } else {
-                        break;
+                    }
+                    if input.peek(syn::Token![,]) {
+                        let _: syn::Token![,] = input.parse()?;
                     }
                 }
                 Ok(())
+            })?;
+        }
+    }
+    if let Some(compress) = &container.compress
+        && container.header.is_none()
+    {
+        return Err(syn::Error::new_spanned(
+            compress,
+            "only types with a `header` can be compressed",
+        ));
+    }
+    let current = match &container.version {
+        Some(syn::Lit::Int(version)) => Some(version.base10_parse::<u16>()?),
+        _ => None,
+    };
+    for (lit, ty) in upgrades {
+        let version: u16 = lit.base10_parse()?;
+        if current.is_some_and(|current| version >= current) {
+            return Err(syn::Error::new_spanned(
+                &lit,
+                "upgrades must be for versions older than `version`",
+            ));
+        }
+        if container
+            .upgrades
+            .iter()
+            .any(|(other, _)| *other == version)
+        {
+            return Err(syn::Error::new_spanned(
+                &lit,
+                format!("duplicate upgrade for version {}", version),
+            ));
+        }
+        container.upgrades.push((version, ty));
+    }
+    container.upgrades.sort_by_key(|(version, _)| *version);
+    Ok(container)
+}
+
+/// Implements `Versioned` for types with a header, reading older versions through the
+/// registered upgrade types and converting them forward one version at a time
+fn versioned_impl(
+    input: &DeriveInput,
+    de_generics: &Generics,
+    container: &ContainerAttrs,
+) -> proc_macro2::TokenStream {
+    let (Some(header), Some(version)) = (&container.header, &container.version) else {
+        return quote! {};
+    };
+    let name = &input.ident;
+    let (impl_generics, _, where_clause) = de_generics.split_for_impl();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+    let min_version = match container.upgrades.first() {
+        Some((min_version, _)) => quote! { #min_version },
+        None => quote! { #version },
+    };
+    let upgrade_arms = container
+        .upgrades
+        .iter()
+        .enumerate()
+        .map(|(i, (from, ty))| {
+            let steps = container.upgrades[i + 1..].iter().map(|(_, next)| {
+                quote! { let value: #next = ::core::convert::From::from(value); }
             });
+            quote! {
+                #from => {
+                    let value = <#ty as oxfmt::Deserializable<'__de>>::deserialize(deserialize)?;
+                    #( #steps )*
+                    Ok(::core::convert::From::from(value))
+                }
+            }
+        });
+    quote! {
+        impl #impl_generics oxfmt::Versioned<'__de> for #name #ty_generics #where_clause {
+            const HEADER: &'static [u8] = #header.as_bytes();
+            const VERSION: u16 = #version as u16;
+            const MIN_VERSION: u16 = #min_version as u16;
+
+            fn upgrade(
+                version: u16,
+                deserialize: &mut oxfmt::Deserialize<'__de>,
+            ) -> ::core::result::Result<Self, oxfmt::Error> {
+                match version {
+                    #( #upgrade_arms )*
+                    version => Err(oxfmt::Error::custom(
+                        format!("no upgrade registered for version {}", version),
+                        deserialize.offset(),
+                    )),
+                }
+            }
         }
     }
+}
+
+/// Field level attributes:
+/// `#[oxfmt(skip, default = ..., from = ..., with = ..., rename = ..., tag = ...)]`
+#[derive(Default)]
+struct FieldAttrs {
+    /// Not written, and read as `default` or `Default::default()`
+    skip: bool,
+    /// The value of skipped fields and of fields missing from tagged structs
+    default: Option<Expr>,
+    /// Read as this type and converted with `TryFrom`
+    from: Option<Type>,
+    /// A module with `serialize_into`, `deserialize` and `describe` functions for the field
+    with: Option<Path>,
+    /// Name of the field in error paths and schemas
+    rename: Option<String>,
+    /// Identifies the field in tagged structs, so it must never be reused for another field
+    tag: Option<u32>,
+}
+
+fn parse_field_attrs(field: &syn::Field, tagged: bool) -> syn::Result<FieldAttrs> {
+    let mut attrs = FieldAttrs::default();
+    for attr in &field.attrs {
+        if !attr.path().is_ident("oxfmt") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                attrs.skip = true;
+            } else if meta.path.is_ident("default") {
+                attrs.default = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("from") {
+                attrs.from = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("with") {
+                attrs.with = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("rename") {
+                let name: LitStr = meta.value()?.parse()?;
+                attrs.rename = Some(name.value());
+            } else if meta.path.is_ident("tag") {
+                let tag: LitInt = meta.value()?.parse()?;
+                attrs.tag = Some(tag.base10_parse()?);
+            } else {
+                return Err(meta.error(
+                    "unknown oxfmt field attribute, expected `skip`, `default`, `from`, `with`, `rename` or `tag`",
+                ));
+            }
+            Ok(())
+        })?;
+    }
+    let conflict =
+        if attrs.skip && (attrs.from.is_some() || attrs.with.is_some() || attrs.tag.is_some()) {
+            Some("skipped fields are never read, so they cannot have `from`, `with` or `tag`")
+        } else if attrs.from.is_some() && attrs.with.is_some() {
+            Some("`from` and `with` cannot be combined")
+        } else if attrs.default.is_some() && !attrs.skip && !tagged {
+            Some("`default` is the value of a skipped field, add `skip`")
+        } else if attrs.tag.is_some() && !tagged {
+            Some("`tag` only applies to fields of `#[oxfmt(tagged)]` structs")
+        } else if attrs.tag.is_none() && !attrs.skip && tagged {
+            Some("fields of tagged structs need a `tag`")
+        } else {
+            None
+        };
+    match conflict {
+        Some(message) => Err(syn::Error::new_spanned(field, message)),
+        None => Ok(attrs),
+    }
+}
+
+fn parse_fields_attrs(fields: &Fields, tagged: bool) -> syn::Result<Vec<FieldAttrs>> {
+    let attrs = fields
+        .iter()
+        .map(|field| parse_field_attrs(field, tagged))
+        .collect::<syn::Result<Vec<_>>>()?;
+    for (i, (field, field_attrs)) in fields.iter().zip(&attrs).enumerate() {
+        if let Some(tag) = field_attrs.tag
+            && attrs[..i].iter().any(|attrs| attrs.tag == Some(tag))
+        {
+            return Err(syn::Error::new_spanned(
+                field,
+                format!("tag {tag} is used by another field"),
+            ));
+        }
+    }
+    Ok(attrs)
+}
+
+/// The name of a field in error paths and schemas, tuple fields are named by their index
+fn field_name(field: &syn::Field, index: usize, attrs: &FieldAttrs) -> String {
+    match (&attrs.rename, &field.ident) {
+        (Some(name), _) => name.clone(),
+        (None, Some(ident)) => ident.to_string(),
+        (None, None) => index.to_string(),
+    }
+}
+
+/// Writes `value`, a reference to a field, into `writer` unless the field is skipped
+fn write_field(attrs: &FieldAttrs, value: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    match &attrs.with {
+        _ if attrs.skip => quote! {},
+        Some(with) => quote! { #with::serialize_into(#value, writer)?; },
+        None => quote! { oxfmt::Serializable::serialize_into(#value, writer)?; },
+    }
+}
+
+/// Adds a field to a `BinaryBuilder` unless it's skipped
+fn add_field(attrs: &FieldAttrs, value: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    match &attrs.with {
+        _ if attrs.skip => quote! {},
+        Some(with) => quote! { .add_with(|writer| #with::serialize_into(#value, writer))? },
+        None => quote! { .add(#value)? },
+    }
+}
+
+/// The size hint of a field, 0 for skipped fields and ones written through a `with` module
+fn hint_field(attrs: &FieldAttrs, value: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    match attrs.skip || attrs.with.is_some() {
+        true => quote! { 0 },
+        false => quote! { oxfmt::Serializable::size_hint(#value) },
+    }
+}
+
+/// The size hint of a tagged struct's fields, counting their tags and lengths
+fn hint_tagged(
+    attrs: &[FieldAttrs],
+    values: impl Iterator<Item = proc_macro2::TokenStream>,
+) -> proc_macro2::TokenStream {
+    let count = attrs.iter().filter(|attrs| !attrs.skip).count();
+    let hints = values
+        .zip(attrs)
+        .filter(|(_, attrs)| !attrs.skip)
+        .map(|(value, attrs)| {
+            let tag = attrs.tag;
+            let hint = hint_field(attrs, value);
+            quote! { oxfmt::tagged::field_size_hint(#tag, #hint) }
+        });
+    quote! { oxfmt::Serializable::size_hint(&#count) #( + #hints )* }
+}
+
+/// Writes the fields of a tagged struct into `writer`, each with its tag and length
+fn write_tagged(
+    attrs: &[FieldAttrs],
+    values: impl Iterator<Item = proc_macro2::TokenStream>,
+) -> proc_macro2::TokenStream {
+    let count = attrs.iter().filter(|attrs| !attrs.skip).count();
+    let writes = values
+        .zip(attrs)
+        .filter(|(_, attrs)| !attrs.skip)
+        .map(|(value, attrs)| {
+            let tag = attrs.tag;
+            let write = write_field(attrs, value);
+            quote! { oxfmt::tagged::write_field(writer, #tag, |writer| { #write Ok(()) })?; }
+        });
+    quote! {
+        oxfmt::tagged::write_count(writer, #count)?;
+        #( #writes )*
+    }
+}
+
+/// Reads the fields of a tagged struct, skipping unknown tags, and builds it with missing
+/// fields set to their default
+fn read_tagged(fields: &Fields, attrs: &[FieldAttrs]) -> proc_macro2::TokenStream {
+    let slots: Vec<_> = (0..attrs.len())
+        .map(|i| format_ident!("__field{}", i))
+        .collect();
+    let mut arms = Vec::new();
+    let mut values = Vec::new();
+    for (i, ((field, attrs), slot)) in fields.iter().zip(attrs).zip(&slots).enumerate() {
+        let default = match &attrs.default {
+            Some(default) => quote! { #default },
+            None => quote! { ::core::default::Default::default() },
+        };
+        if attrs.skip {
+            values.push(default);
+            continue;
+        }
+        let tag = attrs.tag.map(u64::from);
+        let read = read_field(field, attrs, &field_name(field, i, attrs));
+        arms.push(quote! {
+            #tag => {
+                #slot = ::core::option::Option::Some(#read);
+                Ok(true)
+            }
+        });
+        values.push(quote! { #slot.unwrap_or_else(|| #default) });
+    }
+    let value = construct(quote! { Self }, fields, values.into_iter());
+    let declared = slots.iter().zip(attrs).filter(|(_, attrs)| !attrs.skip);
+    let declared = declared.map(|(slot, _)| slot);
+    quote! {
+        #( let mut #declared = ::core::option::Option::None; )*
+        oxfmt::tagged::read_fields(deserialize, |deserialize, tag| match tag {
+            #( #arms )*
+            _ => Ok(false),
+        })?;
+        Ok(#value)
+    }
+}
+
+/// Reads a field, converting it with `TryFrom` if it has `#[oxfmt(from = ...)]`, and adds
+/// the field to the path of any error
+fn read_field(
+    field: &syn::Field,
+    attrs: &FieldAttrs,
+    field_name: &str,
+) -> proc_macro2::TokenStream {
+    if attrs.skip {
+        return match &attrs.default {
+            Some(default) => quote! { #default },
+            None => quote! { ::core::default::Default::default() },
+        };
+    }
+    let field_ty = &field.ty;
+    // Fields with `from` are read as the source type and converted with `TryFrom`,
+    // for options the conversion applies to the inner value
+    let read = match (&attrs.from, option_inner(field_ty)) {
+        _ if attrs.with.is_some() => {
+            let with = &attrs.with;
+            quote! { #with::deserialize(deserialize) }
+        }
+        (Some(from_ty), Some(inner_ty)) => quote! {{
+            let offset = deserialize.offset();
+            <Option<#from_ty> as oxfmt::Deserializable<'__de>>::deserialize(deserialize)
+                .and_then(|value| {
+                    value
+                        .map(<#inner_ty>::try_from)
+                        .transpose()
+                        .map_err(|err| oxfmt::Error::custom(format!("invalid value: {}", err), offset))
+                })
+        }},
+        (Some(from_ty), None) => quote! {{
+            let offset = deserialize.offset();
+            <#from_ty as oxfmt::Deserializable<'__de>>::deserialize(deserialize)
+                .and_then(|value| {
+                    <#field_ty>::try_from(value)
+                        .map_err(|err| oxfmt::Error::custom(format!("invalid value: {}", err), offset))
+                })
+        }},
+        (None, _) => quote! {
+            <#field_ty as oxfmt::Deserializable<'__de>>::deserialize(deserialize)
+        },
+    };
+    quote! { #read.map_err(|err| err.in_field(#field_name))? }
+}
+
+#[proc_macro_derive(Serializable, attributes(oxfmt))]
+pub fn serializable_derive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let generics = match bounded_generics(&input, quote! { oxfmt::Serializable }, false) {
+        Ok(generics) => generics,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+
+    let ContainerAttrs {
+        header,
+        version,
+        checksum,
+        tagged,
+        compress,
+        ..
+    } = match parse_container_attrs(&input) {
+        Ok(attrs) => attrs,
+        Err(err) => return err.to_compile_error().into(),
+    };
 
     let expanded = match &input.data {
         Data::Struct(DataStruct { fields, .. }) => {
-            let add_fields = fields.iter().map(|f| {
-                let fname = &f.ident;
-                quote! { .add(&self.#fname)? }
-            });
+            let attrs = match parse_fields_attrs(fields, tagged) {
+                Ok(attrs) => attrs,
+                Err(err) => return err.to_compile_error().into(),
+            };
+            let values = fields
+                .iter()
+                .enumerate()
+                .map(|(i, field)| match &field.ident {
+                    Some(ident) => quote! { &self.#ident },
+                    None => {
+                        let index = Index::from(i);
+                        quote! { &self.#index }
+                    }
+                });
+            let tagged_writes = tagged.then(|| write_tagged(&attrs, values.clone()));
+            let hint = match tagged {
+                true => hint_tagged(&attrs, values.clone()),
+                false => {
+                    let hints = values
+                        .clone()
+                        .zip(&attrs)
+                        .map(|(value, attrs)| hint_field(attrs, value));
+                    quote! { 0 #( + #hints )* }
+                }
+            };
             if let (Some(header), Some(version)) = (header, version) {
+                let adds: Vec<_> = match tagged_writes {
+                    Some(writes) => vec![quote! { .add_with(|writer| { #writes Ok(()) })? }],
+                    None => values
+                        .zip(&attrs)
+                        .map(|(value, attrs)| add_field(attrs, value))
+                        .collect(),
+                };
+                let with_checksum = checksum.then(|| quote! { .with_checksum() });
+                let with_compression = compress.map(|compression| {
+                    quote! { .with_compression(oxfmt::Compression::#compression)? }
+                });
                 quote! {
                     impl #impl_generics oxfmt::Serializable for #name #ty_generics #where_clause {
-                        fn serialize(&self) -> anyhow::Result<Box<[u8]>> {
-                            let result = oxfmt::BinaryBuilder::new(
+                        fn serialize_into<W: std::io::Write + ?Sized>(
+                            &self,
+                            writer: &mut W,
+                        ) -> oxfmt::anyhow::Result<()> {
+                            oxfmt::BinaryBuilder::from_writer(
+                                writer,
                                 #header.as_bytes(),
                                 #version as u16
                             )
-                                #(#add_fields)*
-                                .build();
-                            Ok(result)
+                                #with_checksum
+                                #with_compression
+                                #( #adds )*
+                                .finish()?;
+                            Ok(())
+                        }
+
+                        fn size_hint(&self) -> usize {
+                            oxfmt::BinaryBuilder::framing_len(#header.as_bytes(), #checksum) + #hint
                         }
                     }
                 }
             } else {
+                let writes = match tagged_writes {
+                    Some(writes) => writes,
+                    None => {
+                        let writes = values
+                            .zip(&attrs)
+                            .map(|(value, attrs)| write_field(attrs, value));
+                        quote! { #( #writes )* }
+                    }
+                };
                 quote! {
                     impl #impl_generics oxfmt::Serializable for #name #ty_generics #where_clause {
-                        fn serialize(&self) -> anyhow::Result<Box<[u8]>> {
-                            let result = oxfmt::BinaryBuilder::new_no_meta()
-                                #(#add_fields)*
-                                .build();
-                            Ok(result)
+                        fn serialize_into<W: std::io::Write + ?Sized>(
+                            &self,
+                            writer: &mut W,
+                        ) -> oxfmt::anyhow::Result<()> {
+                            #writes
+                            Ok(())
+                        }
+
+                        fn size_hint(&self) -> usize {
+                            #hint
                         }
                     }
                 }
             }
         }
-        Data::Enum(DataEnum { .. }) => {
-            if !has_repr_u8(&input.attrs) {
-                return syn::Error::new_spanned(
-                    &input.ident,
-                    "Serializable can only be derived for #[repr(u8)] enums",
-                )
-                .to_compile_error()
-                .into();
+        Data::Enum(DataEnum { variants, .. }) if !is_fieldless_repr_u8(&input.attrs, variants) => {
+            if let Err(err) = check_variant_count(&input.ident, variants) {
+                return err.to_compile_error().into();
             }
+            let variant_attrs = match variants
+                .iter()
+                .map(|variant| parse_fields_attrs(&variant.fields, false))
+                .collect::<syn::Result<Vec<_>>>()
+            {
+                Ok(attrs) => attrs,
+                Err(err) => return err.to_compile_error().into(),
+            };
+            let (arms, hint_arms): (Vec<_>, Vec<_>) = variants
+                .iter()
+                .zip(&variant_attrs)
+                .enumerate()
+                .map(|(tag, (variant, attrs))| {
+                    let tag = tag as u8;
+                    let ident = &variant.ident;
+                    // Skipped fields aren't bound, so they don't show up as unused
+                    let bindings: Vec<_> = attrs
+                        .iter()
+                        .enumerate()
+                        .map(|(i, attrs)| match attrs.skip {
+                            true => quote! { _ },
+                            false => format_ident!("__field{}", i).into_token_stream(),
+                        })
+                        .collect();
+                    let pattern = match &variant.fields {
+                        Fields::Named(fields) => {
+                            let names = fields.named.iter().map(|f| &f.ident);
+                            quote! { Self::#ident { #( #names: #bindings ),* } }
+                        }
+                        Fields::Unnamed(_) => quote! { Self::#ident( #( #bindings ),* ) },
+                        Fields::Unit => quote! { Self::#ident },
+                    };
+                    let writes = bindings
+                        .iter()
+                        .zip(attrs)
+                        .map(|(binding, attrs)| write_field(attrs, binding.clone()));
+                    let hints = bindings
+                        .iter()
+                        .zip(attrs)
+                        .map(|(binding, attrs)| hint_field(attrs, binding.clone()));
+                    let arm = quote! {
+                        #pattern => {
+                            writer.write_all(&[#tag])?;
+                            #( #writes )*
+                        }
+                    };
+                    (arm, quote! { #pattern => 1 #( + #hints )*, })
+                })
+                .unzip();
             quote! {
                 impl #impl_generics oxfmt::Serializable for #name #ty_generics #where_clause {
-                    fn serialize(&self) -> anyhow::Result<Box<[u8]>> {
-                        Ok(Box::new([*self as u8]))
+                    fn serialize_into<W: std::io::Write + ?Sized>(
+                        &self,
+                        writer: &mut W,
+                    ) -> oxfmt::anyhow::Result<()> {
+                        match self {
+                            #( #arms )*
+                        }
+                        Ok(())
+                    }
+
+                    fn size_hint(&self) -> usize {
+                        match self {
+                            #( #hint_arms )*
+                        }
+                    }
+                }
+            }
+        }
+        Data::Enum(DataEnum { .. }) => {
+            quote! {
+                impl #impl_generics oxfmt::Serializable for #name #ty_generics #where_clause {
+                    fn serialize_into<W: std::io::Write + ?Sized>(
+                        &self,
+                        writer: &mut W,
+                    ) -> oxfmt::anyhow::Result<()> {
+                        Ok(writer.write_all(&[*self as u8])?)
+                    }
+
+                    fn size_hint(&self) -> usize {
+                        1
                     }
                 }
             }
         }
         _ => syn::Error::new_spanned(
             &input.ident,
-            "Serializable can only be derived for structs and #[repr(u8)] enums",
+            "Serializable can only be derived for structs and enums",
         )
         .to_compile_error(),
     };
@@ -121,76 +768,304 @@ pub fn serializable_derive(input: TokenStream) -> TokenStream {
 pub fn deserializable_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let name = &input.ident;
-    let generics = &input.generics;
-    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let de_generics = match bounded_generics(&input, quote! { oxfmt::Deserializable<'__de> }, true)
+    {
+        Ok(generics) => de_generics(&generics),
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let (impl_generics, _, where_clause) = de_generics.split_for_impl();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+
+    let container = match parse_container_attrs(&input) {
+        Ok(attrs) => attrs,
+        Err(err) => return err.to_compile_error().into(),
+    };
 
     let expanded = match &input.data {
         Data::Struct(DataStruct { fields, .. }) => {
-            let mut structure_fields = Vec::new();
-            let mut construct_fields = Vec::new();
-            for field in fields {
-                let field_ident = field.ident.as_ref().expect("Expected named field");
-                let mut mapping = None;
-                let mut from_ty = None;
-                for attr in &field.attrs {
-                    if attr.path().is_ident("oxfmt") {
-                        let _ = attr.parse_args_with(|input: syn::parse::ParseStream| {
-                            while !input.is_empty() {
-                                let lookahead = input.lookahead1();
-                                if lookahead.peek(syn::Ident) {
-                                    let ident: syn::Ident = input.parse()?;
-                                    let _eq: syn::Token![=] = input.parse()?;
-                                    let value: syn::Expr = input.parse()?;
-                                    match ident.to_string().as_str() {
-                                        "mapping" => mapping = Some(quote! { #value }),
-                                        "from" => from_ty = Some(quote! { #value }),
-                                        _ => {}
-                                    }
-                                    if input.peek(syn::Token![,]) {
-                                        let _: syn::Token![,] = input.parse()?;
-                                    }
-                                } else {
-                                    break;
-                                }
-                            }
-                            Ok(())
-                        });
+            let attrs = match parse_fields_attrs(fields, container.tagged) {
+                Ok(attrs) => attrs,
+                Err(err) => return err.to_compile_error().into(),
+            };
+            let body = if container.tagged {
+                read_tagged(fields, &attrs)
+            } else {
+                let reads = fields
+                    .iter()
+                    .zip(&attrs)
+                    .enumerate()
+                    .map(|(i, (field, attrs))| {
+                        read_field(field, attrs, &field_name(field, i, attrs))
+                    });
+                let value = construct(quote! { Self }, fields, reads);
+                quote! { Ok(#value) }
+            };
+            let versioned = versioned_impl(&input, &de_generics, &container);
+            quote! {
+                impl #impl_generics oxfmt::Deserializable<'__de> for #name #ty_generics #where_clause {
+                    fn deserialize(
+                        deserialize: &mut oxfmt::Deserialize<'__de>,
+                    ) -> ::core::result::Result<Self, oxfmt::Error> {
+                        deserialize.nested(|deserialize| { #body })
                     }
                 }
-                let mapping = mapping.unwrap_or_else(|| {
-                    syn::Error::new_spanned(
-                        &field,
-                        "Missing #[oxfmt(mapping = ...)] attribute on field",
-                    )
-                    .to_compile_error()
-                });
-                let field_ty = &field.ty;
-                let from_ty = from_ty.unwrap_or_else(|| quote! { #field_ty });
-                // Only use 'as' if the types differ
-                let construct_field = if quote! { #field_ty }.to_string() != from_ty.to_string() {
-                    quote! { #field_ident: #field_ty as #from_ty }
-                } else {
-                    quote! { #field_ident: #field_ty as #field_ty }
-                };
-                structure_fields.push(mapping);
-                construct_fields.push(construct_field);
+
+                #versioned
+            }
+        }
+        Data::Enum(DataEnum { variants, .. }) if !is_fieldless_repr_u8(&input.attrs, variants) => {
+            if let Err(err) = check_variant_count(&input.ident, variants) {
+                return err.to_compile_error().into();
             }
+            let variant_attrs = match variants
+                .iter()
+                .map(|variant| parse_fields_attrs(&variant.fields, false))
+                .collect::<syn::Result<Vec<_>>>()
+            {
+                Ok(attrs) => attrs,
+                Err(err) => return err.to_compile_error().into(),
+            };
+            let arms =
+                variants
+                    .iter()
+                    .zip(&variant_attrs)
+                    .enumerate()
+                    .map(|(tag, (variant, attrs))| {
+                        let tag = tag as u8;
+                        let ident = &variant.ident;
+                        let reads = variant.fields.iter().zip(attrs).enumerate().map(
+                            |(i, (field, attrs))| {
+                                read_field(field, attrs, &field_name(field, i, attrs))
+                            },
+                        );
+                        let value = construct(quote! { Self::#ident }, &variant.fields, reads);
+                        quote! { #tag => Ok(#value), }
+                    });
+            let enum_name = name.to_string();
             quote! {
-                impl #impl_generics oxfmt::Deserializable for #name #ty_generics #where_clause {
-                    fn get_structure() -> oxfmt::Structure {
-                        oxfmt::structure!( #( #structure_fields ),* )
+                impl #impl_generics oxfmt::Deserializable<'__de> for #name #ty_generics #where_clause {
+                    fn deserialize(
+                        deserialize: &mut oxfmt::Deserialize<'__de>,
+                    ) -> ::core::result::Result<Self, oxfmt::Error> {
+                        deserialize.nested(|deserialize| {
+                            let offset = deserialize.offset();
+                            match deserialize.read_u8()? {
+                                #( #arms )*
+                                value => Err(oxfmt::Error::new(
+                                    oxfmt::ErrorKind::InvalidDiscriminant { ty: #enum_name, value },
+                                    offset,
+                                )),
+                            }
+                        })
                     }
-                    fn construct(mut fields: Vec<Box<dyn std::any::Any>>) -> anyhow::Result<Self> {
-                        oxfmt::construct!(fields, #( #construct_fields ),* )
+                }
+            }
+        }
+        Data::Enum(DataEnum { variants, .. }) => {
+            let variant_idents: Vec<_> = variants.iter().map(|v| &v.ident).collect();
+            let enum_name = name.to_string();
+            quote! {
+                impl #impl_generics oxfmt::Deserializable<'__de> for #name #ty_generics #where_clause {
+                    fn deserialize(
+                        deserialize: &mut oxfmt::Deserialize<'__de>,
+                    ) -> ::core::result::Result<Self, oxfmt::Error> {
+                        let offset = deserialize.offset();
+                        match deserialize.read_u8()? {
+                            #( value if value == Self::#variant_idents as u8 => Ok(Self::#variant_idents), )*
+                            value => Err(oxfmt::Error::new(
+                                oxfmt::ErrorKind::InvalidDiscriminant { ty: #enum_name, value },
+                                offset,
+                            )),
+                        }
                     }
                 }
             }
         }
         _ => syn::Error::new_spanned(
             &input.ident,
-            "Deserializable can only be derived for structs with #[oxfmt(mapping = ...)] attributes on fields",
+            "Deserializable can only be derived for structs and enums",
         )
         .to_compile_error(),
     };
     TokenStream::from(expanded)
 }
+
+/// Describes the fields of a struct or enum variant, tuple fields are named by their index
+fn describe_structure(name: &Ident, fields: &Fields) -> syn::Result<proc_macro2::TokenStream> {
+    let name = name.to_string();
+    let attrs = parse_fields_attrs(fields, false)?;
+    let fields = fields
+        .iter()
+        .zip(&attrs)
+        .enumerate()
+        .filter(|(_, (_, attrs))| !attrs.skip)
+        .map(|(i, (field, attrs))| {
+            let field_name = field_name(field, i, attrs);
+            let describe = describe_field(field, attrs);
+            quote! { (::std::string::String::from(#field_name), #describe) }
+        });
+    Ok(quote! {
+        oxfmt::Structure {
+            name: ::std::string::String::from(#name),
+            fields: ::std::vec![ #( #fields ),* ],
+        }
+    })
+}
+
+/// Describes a tagged struct, with the tag of each field
+fn describe_tagged(name: &Ident, fields: &Fields) -> syn::Result<proc_macro2::TokenStream> {
+    let name = name.to_string();
+    let attrs = parse_fields_attrs(fields, true)?;
+    let fields = fields
+        .iter()
+        .zip(&attrs)
+        .enumerate()
+        .filter(|(_, (_, attrs))| !attrs.skip)
+        .map(|(i, (field, attrs))| {
+            let tag = attrs.tag;
+            let field_name = field_name(field, i, attrs);
+            let describe = describe_field(field, attrs);
+            quote! { (#tag, ::std::string::String::from(#field_name), #describe) }
+        });
+    Ok(quote! {
+        oxfmt::Field::Tagged {
+            name: ::std::string::String::from(#name),
+            fields: ::std::vec![ #( #fields ),* ],
+        }
+    })
+}
+
+fn describe_field(field: &syn::Field, attrs: &FieldAttrs) -> proc_macro2::TokenStream {
+    let ty = &field.ty;
+    match &attrs.with {
+        Some(with) => quote! { #with::describe() },
+        None => quote! { <#ty as oxfmt::Describe>::describe() },
+    }
+}
+
+#[proc_macro_derive(Describe, attributes(oxfmt))]
+pub fn describe_derive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let generics = match bounded_generics(&input, quote! { oxfmt::Describe }, false) {
+        Ok(generics) => generics,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+
+    let tagged = match parse_container_attrs(&input) {
+        Ok(container) => container.tagged,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let field = match &input.data {
+        Data::Struct(DataStruct { fields, .. }) if tagged => match describe_tagged(name, fields) {
+            Ok(field) => field,
+            Err(err) => return err.to_compile_error().into(),
+        },
+        Data::Struct(DataStruct { fields, .. }) => match describe_structure(name, fields) {
+            Ok(structure) => quote! { oxfmt::Field::Struct(#structure) },
+            Err(err) => return err.to_compile_error().into(),
+        },
+        Data::Enum(DataEnum { variants, .. }) if !is_fieldless_repr_u8(&input.attrs, variants) => {
+            let enum_name = name.to_string();
+            let variants = match variants
+                .iter()
+                .map(|variant| describe_structure(&variant.ident, &variant.fields))
+                .collect::<syn::Result<Vec<_>>>()
+            {
+                Ok(variants) => variants,
+                Err(err) => return err.to_compile_error().into(),
+            };
+            quote! {
+                oxfmt::Field::Enum {
+                    name: ::std::string::String::from(#enum_name),
+                    variants: ::std::vec![ #( #variants ),* ],
+                }
+            }
+        }
+        Data::Enum(DataEnum { variants, .. }) => {
+            let enum_name = name.to_string();
+            let variants = variants.iter().map(|variant| {
+                let ident = &variant.ident;
+                let variant_name = ident.to_string();
+                quote! { (::std::string::String::from(#variant_name), Self::#ident as u8) }
+            });
+            quote! {
+                oxfmt::Field::Repr {
+                    name: ::std::string::String::from(#enum_name),
+                    variants: ::std::vec![ #( #variants ),* ],
+                }
+            }
+        }
+        _ => {
+            return syn::Error::new_spanned(
+                &input.ident,
+                "Describe can only be derived for structs and enums",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+    let type_name = name.to_string();
+    TokenStream::from(quote! {
+        impl #impl_generics oxfmt::Describe for #name #ty_generics #where_clause {
+            fn describe() -> oxfmt::Field {
+                oxfmt::describe_named::<Self>(#type_name, || #field)
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upgrade_error(input: DeriveInput) -> String {
+        match parse_container_attrs(&input) {
+            Ok(_) => panic!("expected the attributes to be rejected"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn upgrades_are_sorted_by_version() {
+        let input: DeriveInput = syn::parse_quote! {
+            #[oxfmt(header = "test", version = 3, upgrade(2 = V2, 1 = V1))]
+            struct Test;
+        };
+        let container = parse_container_attrs(&input).unwrap();
+        let versions: Vec<u16> = container
+            .upgrades
+            .iter()
+            .map(|(version, _)| *version)
+            .collect();
+        assert_eq!(versions, [1, 2]);
+    }
+
+    #[test]
+    fn upgrade_versions_are_validated() {
+        let current: DeriveInput = syn::parse_quote! {
+            #[oxfmt(header = "test", version = 2, upgrade(1 = V1, 2 = V2))]
+            struct Test;
+        };
+        assert_eq!(
+            upgrade_error(current),
+            "upgrades must be for versions older than `version`"
+        );
+        let newer: DeriveInput = syn::parse_quote! {
+            #[oxfmt(upgrade(3 = V3), header = "test", version = 2)]
+            struct Test;
+        };
+        assert_eq!(
+            upgrade_error(newer),
+            "upgrades must be for versions older than `version`"
+        );
+        let duplicate: DeriveInput = syn::parse_quote! {
+            #[oxfmt(header = "test", version = 3, upgrade(1 = V1, 1 = Other))]
+            struct Test;
+        };
+        assert_eq!(upgrade_error(duplicate), "duplicate upgrade for version 1");
+    }
+}
@@ -25,4 +25,8 @@ pub enum Subcommands {
         path: Option<PathBuf>,
     },
     Check,
+    Schema {
+        #[clap(short = 'd')]
+        destination: Option<PathBuf>,
+    },
 }
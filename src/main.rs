@@ -5,7 +5,9 @@ mod pack;
 mod structs;
 
 use std::{
-    env, fs,
+    env,
+    fs::{self, File},
+    io::{BufWriter, Seek, Write},
     path::{Path, PathBuf},
     process,
 };
@@ -14,16 +16,17 @@ use anyhow::{anyhow, Result};
 use clap::Parser;
 
 use colored::Colorize;
-use oxfmt::Serializable;
+use oxfmt::{Schema, Serializable};
+use tempfile::tempfile;
 
 use check::assert_valid_config;
 use cli::{RilipakCli, Subcommands};
-use pack::{read_exclude, zip_dir};
+use pack::{read_exclude, read_pack, unzip_dir, write_pack, zip_dir};
 use structs::{Pack, PackConfig, PackMeta};
 
 fn main() {
     if let Err(err) = __main() {
-        eprintln!("{}", err.to_string().red().bold());
+        eprintln!("{}", format!("{err:#}").red().bold());
         process::exit(1);
     }
 }
@@ -33,12 +36,12 @@ fn __main() -> Result<()> {
 
     match cli.subcommand {
         Subcommands::Build { destination } => build(destination),
-        //Subcommands::Install { file, destination } => install(file, destination),
+        Subcommands::Install { file, destination } => install(file, destination),
         Subcommands::Init { path } => {
             init(path).map_err(|err| anyhow!("Failed to create files: {err}"))
         }
         Subcommands::Check => check(),
-        _ => todo!(),
+        Subcommands::Schema { destination } => schema(destination),
     }
 }
 
@@ -83,16 +86,13 @@ fn build(destination: Option<PathBuf>) -> Result<()> {
     }
 
     let exclude = read_exclude()?;
-    let files = zip_dir(Path::new("./"), &exclude)?;
+    let mut include = tempfile()?;
+    zip_dir(Path::new("./"), &exclude, &mut include)?;
 
-    let pack: Pack = Pack {
-        meta: PackMeta { config, modbuilds },
-        include: files.into_boxed_slice(),
-    };
+    let meta = PackMeta { config, modbuilds };
 
-    let serialized = pack.serialize()?;
-
-    fs::write(destination, serialized)?;
+    let file = BufWriter::new(File::create(destination)?);
+    write_pack(file, &meta, include)?.flush()?;
 
     Ok(())
 }
@@ -104,6 +104,169 @@ fn check() -> Result<()> {
     assert_valid_config(&config)
 }
 
-//fn install(file: PathBuf, destination: Option<PathBuf>) -> Result<()> {
-//    todo!()
-//}
+/// Writes the schema of `.rilipak` files, so they can be inspected with `oxfmt dump`
+fn schema(destination: Option<PathBuf>) -> Result<()> {
+    let destination = destination.unwrap_or(PathBuf::from("rilipak.oxschema"));
+    fs::write(destination, Schema::of::<Pack>().serialize()?)?;
+    Ok(())
+}
+
+fn install(file: PathBuf, destination: Option<PathBuf>) -> Result<()> {
+    let mut include = tempfile()?;
+    let meta = read_pack(File::open(file)?, &mut include)?;
+    include.rewind()?;
+
+    let id = meta.config.id.clone();
+    let destination = destination.unwrap_or(PathBuf::from(&id));
+
+    fs::create_dir_all(&destination)?;
+    unzip_dir(include, &destination)?;
+
+    let mods = destination.join("mods");
+    fs::create_dir_all(&mods)?;
+
+    for modbuild in &meta.modbuilds {
+        let built = mcmodbuild::build(modbuild)?;
+        let name = built
+            .file_name()
+            .ok_or_else(|| anyhow!("Built mod has no file name: {}", built.display()))?;
+        fs::copy(&built, mods.join(name))?;
+    }
+
+    println!(
+        "{}{}",
+        "Successfully installed modpack at ".green(),
+        destination.to_string_lossy().bright_green().bold()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use oxfmt::Versioned;
+    use proptest::{collection::vec, prelude::*};
+
+    use crate::structs::{Mod, ModEnv, ModLoader, ModSource};
+
+    use super::*;
+
+    fn test_pack() -> Pack {
+        Pack {
+            meta: PackMeta {
+                config: PackConfig {
+                    id: "testpack".into(),
+                    name: "Test pack".into(),
+                    author: "Me".into(),
+                    version: "1.0.0".into(),
+                    loader: ModLoader::Quilt,
+                    mods: vec![
+                        Mod {
+                            source: ModSource::Modrinth,
+                            id: "sodium".into(),
+                            env: ModEnv::Client,
+                        },
+                        Mod {
+                            source: ModSource::Github,
+                            id: "septechx/testmod".into(),
+                            env: ModEnv::Common,
+                        },
+                    ],
+                },
+                modbuilds: vec![Box::new([1, 2, 3]), Box::new([]), Box::new([0; 16])],
+            },
+            include: Box::new([0x50, 0x4b, 0x05, 0x06, 0, 0, 0, 0]),
+        }
+    }
+
+    #[test]
+    fn roundtrip_serialize_deserialize() {
+        let pack = test_pack();
+        let serialized = pack.serialize().unwrap();
+        let deserialized = Pack::from_bytes(&serialized).unwrap();
+        assert_eq!(pack, deserialized);
+    }
+
+    #[test]
+    fn streamed_pack_matches_serialize() {
+        let pack = test_pack();
+        let streamed = write_pack(Vec::new(), &pack.meta, Cursor::new(&pack.include)).unwrap();
+        assert_eq!(&*streamed, &*pack.serialize().unwrap());
+
+        let mut include = Vec::new();
+        let meta = read_pack(&*streamed, &mut include).unwrap();
+        assert_eq!(meta, pack.meta);
+        assert_eq!(&*include, &*pack.include);
+    }
+
+    #[test]
+    fn include_is_not_limited_like_the_metadata() {
+        let pack = test_pack();
+        // Past the total size the metadata is limited to
+        let include = vec![0; 64 * 1024 * 1024 + 1];
+        let streamed = write_pack(Vec::new(), &pack.meta, Cursor::new(&include)).unwrap();
+
+        let mut read = Vec::new();
+        let meta = read_pack(&*streamed, &mut read).unwrap();
+        assert_eq!(meta, pack.meta);
+        assert_eq!(read.len(), include.len());
+    }
+
+    #[test]
+    fn serde_matches_derives() {
+        let config = test_pack().meta.config;
+        let mut buf = Vec::new();
+        oxfmt::serde::to_writer(&mut buf, &config).unwrap();
+        assert_eq!(&*buf, &*config.serialize().unwrap());
+    }
+
+    fn pack_config() -> impl Strategy<Value = PackConfig> {
+        let loader = prop_oneof![
+            Just(ModLoader::Fabric),
+            Just(ModLoader::Forge),
+            Just(ModLoader::Neoforge),
+            Just(ModLoader::Quilt),
+        ];
+        let source = prop_oneof![
+            Just(ModSource::Curseforge),
+            Just(ModSource::Modrinth),
+            Just(ModSource::Github),
+        ];
+        let env = prop_oneof![
+            Just(ModEnv::Server),
+            Just(ModEnv::Client),
+            Just(ModEnv::Common),
+        ];
+        let mods = vec(
+            (source, any::<String>(), env).prop_map(|(source, id, env)| Mod { source, id, env }),
+            0..8,
+        );
+        (any::<[String; 4]>(), loader, mods).prop_map(
+            |([id, name, author, version], loader, mods)| PackConfig {
+                id,
+                name,
+                author,
+                version,
+                loader,
+                mods,
+            },
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_configs_roundtrip(config in pack_config(), include in vec(any::<u8>(), 0..64)) {
+            let pack = Pack {
+                meta: PackMeta {
+                    config,
+                    modbuilds: vec![include.clone().into_boxed_slice()],
+                },
+                include: include.into_boxed_slice(),
+            };
+            let serialized = pack.serialize().unwrap();
+            prop_assert_eq!(Pack::from_bytes(&serialized).unwrap(), pack);
+        }
+    }
+}
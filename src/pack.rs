@@ -1,12 +1,15 @@
 use std::{
     fs::{self, File},
-    io::{Cursor, Read, Write},
+    io::{Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
 };
 
 use anyhow::Result;
+use oxfmt::{BinaryBuilder, Deserialize, Limits, Versioned};
 
-use zip::{CompressionMethod, ZipWriter, write::FileOptions};
+use zip::{CompressionMethod, ZipArchive, ZipWriter, write::FileOptions};
+
+use crate::structs::{Pack, PackMeta};
 
 pub fn read_exclude() -> Result<Vec<PathBuf>> {
     let mut exclude = vec![
@@ -24,9 +27,8 @@ pub fn read_exclude() -> Result<Vec<PathBuf>> {
     Ok(exclude)
 }
 
-pub fn zip_dir(base_dir: &Path, exclude: &[PathBuf]) -> Result<Vec<u8>> {
-    let mut buf = Cursor::new(Vec::new());
-    let mut zip = ZipWriter::new(&mut buf);
+pub fn zip_dir<W: Write + Seek>(base_dir: &Path, exclude: &[PathBuf], writer: W) -> Result<()> {
+    let mut zip = ZipWriter::new(writer);
 
     let options = FileOptions::default()
         .compression_method(CompressionMethod::Deflated)
@@ -44,13 +46,61 @@ pub fn zip_dir(base_dir: &Path, exclude: &[PathBuf]) -> Result<Vec<u8>> {
     )?;
 
     zip.finish()?;
-    Ok(buf.into_inner())
+    Ok(())
+}
+
+pub fn unzip_dir<R: Read + Seek>(reader: R, destination: &Path) -> Result<()> {
+    let mut zip = ZipArchive::new(reader)?;
+    zip.extract(destination)?;
+    Ok(())
+}
+
+/// Writes a pack with the same layout as `Pack::serialize`, streaming `include` from a zip
+/// instead of holding it in memory
+pub fn write_pack<W: Write, R: Read + Seek>(
+    writer: W,
+    meta: &PackMeta,
+    mut include: R,
+) -> Result<W> {
+    let len = include.seek(SeekFrom::End(0))?;
+    include.rewind()?;
+    BinaryBuilder::from_writer(writer, Pack::HEADER, Pack::VERSION)
+        .with_checksum()
+        .add(meta)?
+        .add_blob_from(include, len)?
+        .finish()
+}
+
+/// Packs are downloaded, so the metadata is bounded. `include` is streamed and can be any size,
+/// the limits are lifted once the metadata is read.
+const PACK_LIMITS: Limits = Limits {
+    max_len: 64 * 1024,
+    max_string_len: 64 * 1024,
+    max_total_bytes: 64 * 1024 * 1024,
+    max_depth: 32,
+};
+
+/// Reads a pack written by `write_pack`, copying `include` into `writer` and verifying the
+/// checksum before returning
+pub fn read_pack<R: Read, W: Write>(reader: R, mut include: W) -> Result<PackMeta> {
+    let versions = Pack::MIN_VERSION..=Pack::VERSION;
+    let mut deserialize =
+        Deserialize::from_reader(reader, Pack::HEADER, versions)?.with_limits(PACK_LIMITS);
+    let meta = deserialize
+        .read_struct()
+        .map_err(|err| err.in_field("meta"))?;
+    let mut deserialize = deserialize.with_limits(Limits::default());
+    deserialize
+        .read_blob_into(&mut include)
+        .map_err(|err| err.in_field("include"))?;
+    deserialize.finish()?;
+    Ok(meta)
 }
 
-fn visit_dirs(
+fn visit_dirs<W: Write + Seek>(
     base_dir: &Path,
     path: &Path,
-    zip: &mut ZipWriter<&mut Cursor<Vec<u8>>>,
+    zip: &mut ZipWriter<W>,
     options: &FileOptions<()>,
     buffer: &mut Vec<u8>,
     exclude: &[PathBuf],
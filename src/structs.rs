@@ -1,8 +1,9 @@
-use anyhow::{bail, Result};
-use oxfmt::Serializable;
+use oxfmt::{Describe, Deserializable, Serializable};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Serializable)]
+#[derive(
+    Debug, Serialize, Deserialize, Clone, PartialEq, Serializable, Deserializable, Describe,
+)]
 pub struct PackConfig {
     pub id: String,
     pub name: String,
@@ -25,7 +26,9 @@ impl Default for PackConfig {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Serializable)]
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Serializable, Deserializable, Describe,
+)]
 #[repr(u8)]
 pub enum ModLoader {
     Fabric = 0,
@@ -34,27 +37,18 @@ pub enum ModLoader {
     Quilt = 3,
 }
 
-impl TryFrom<u8> for ModLoader {
-    type Error = anyhow::Error;
-    fn try_from(v: u8) -> Result<Self> {
-        match v {
-            0 => Ok(ModLoader::Fabric),
-            1 => Ok(ModLoader::Forge),
-            2 => Ok(ModLoader::Neoforge),
-            3 => Ok(ModLoader::Quilt),
-            other => bail!("invalid mod loader: {}", other),
-        }
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Serializable)]
+#[derive(
+    Debug, Serialize, Deserialize, Clone, PartialEq, Serializable, Deserializable, Describe,
+)]
 pub struct Mod {
     pub source: ModSource,
     pub id: String,
     pub env: ModEnv,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Serializable)]
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Serializable, Deserializable, Describe,
+)]
 #[repr(u8)]
 pub enum ModSource {
     Curseforge = 0,
@@ -62,19 +56,9 @@ pub enum ModSource {
     Github = 2,
 }
 
-impl TryFrom<u8> for ModSource {
-    type Error = anyhow::Error;
-    fn try_from(v: u8) -> Result<Self> {
-        match v {
-            0 => Ok(ModSource::Curseforge),
-            1 => Ok(ModSource::Modrinth),
-            2 => Ok(ModSource::Github),
-            other => bail!("invalid mod source: {}", other),
-        }
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Serializable)]
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Serializable, Deserializable, Describe,
+)]
 #[repr(u8)]
 pub enum ModEnv {
     Server = 0,
@@ -82,26 +66,14 @@ pub enum ModEnv {
     Common = 2,
 }
 
-impl TryFrom<u8> for ModEnv {
-    type Error = anyhow::Error;
-    fn try_from(v: u8) -> Result<Self> {
-        match v {
-            0 => Ok(ModEnv::Server),
-            1 => Ok(ModEnv::Client),
-            2 => Ok(ModEnv::Common),
-            other => bail!("invalid mod source: {}", other),
-        }
-    }
-}
-
-#[derive(Debug, Clone, PartialEq, Serializable)]
-#[oxfmt(header = "rilipak", version = 1)]
+#[derive(Debug, Clone, PartialEq, Serializable, Deserializable, Describe)]
+#[oxfmt(header = "rilipak", version = 1, checksum)]
 pub struct Pack {
     pub meta: PackMeta,
     pub include: Box<[u8]>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serializable)]
+#[derive(Debug, Clone, PartialEq, Serializable, Deserializable, Describe)]
 pub struct PackMeta {
     pub config: PackConfig,
     pub modbuilds: Vec<Box<[u8]>>,